@@ -0,0 +1 @@
+INSERT INTO players (name, position, kit_number) VALUES ({{ Name | sql }}, {{ Position | sql }}, {{ row["Kit Number"] | sql }});
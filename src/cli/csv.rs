@@ -1,37 +1,348 @@
 use super::verify_file;
-use crate::{process_csv, CmdExecutor};
+use crate::{
+    process_csv, process_to_csv, CmdExecutor, CsvCatOpts, CsvDedupOpts, CsvDiffOpts, CsvFakeOpts,
+    CsvGroupOpts, CsvHeadOpts, CsvJoinOpts, CsvPivotOpts, CsvQueryOpts, CsvRenderOpts,
+    CsvSampleOpts, CsvShowOpts, CsvSplitOpts, CsvStatsOpts, CsvTailOpts, CsvValidateOpts, Expr,
+    OutputFile, SortKey,
+};
 use anyhow::anyhow;
-use clap::Parser;
+use clap::{ArgAction, Args, Parser};
+use encoding_rs::Encoding;
+use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::Path;
 use std::str::FromStr;
 
 #[derive(Debug, Copy, Clone)]
 pub enum OutputFormat {
     Json,
     Yaml,
+    /// One json object per line
+    Ndjson,
+    /// An array of tables named `rows`
+    Toml,
+    Msgpack,
+    Cbor,
+    Csv,
+    /// Columnar, with a schema inferred from the rows
+    Parquet,
+    /// Arrow ipc file
+    Arrow,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputFormat {
+    Csv,
+    /// An array of objects
+    Json,
+    /// A sequence of mappings
+    Yaml,
+    /// One json object per line
+    Ndjson,
+}
+
+// 不带子命令时 `rcli csv -i ...` 仍然是格式转换
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
-    pub input: String,
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+    #[command(flatten)]
+    pub convert: CsvConvertOpts,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum CsvSubCommand {
+    #[command(about = "Show CSV as an aligned table")]
+    Show(CsvShowOpts),
+    #[command(about = "Validate CSV rows against a yaml schema")]
+    Validate(CsvValidateOpts),
+    #[command(about = "Profile every column of a CSV file")]
+    Stats(CsvStatsOpts),
+    #[command(about = "Run SQL over one or more CSV files")]
+    Query(CsvQueryOpts),
+    #[command(about = "Compare two CSV files row by row on key columns")]
+    Diff(CsvDiffOpts),
+    #[command(about = "Join two CSV files on key columns")]
+    Join(CsvJoinOpts),
+    #[command(about = "Concatenate CSV files, reconciling their headers")]
+    Cat(CsvCatOpts),
+    #[command(about = "Drop duplicate rows")]
+    Dedup(CsvDedupOpts),
+    #[command(about = "Split a CSV file into smaller files, by row count or column value")]
+    Split(CsvSplitOpts),
+    #[command(about = "Randomly sample rows")]
+    Sample(CsvSampleOpts),
+    #[command(about = "Print the first rows")]
+    Head(CsvHeadOpts),
+    #[command(about = "Print the last rows")]
+    Tail(CsvTailOpts),
+    #[command(about = "Generate fake rows from a yaml schema")]
+    Fake(CsvFakeOpts),
+    #[command(about = "Render rows through a template")]
+    Render(CsvRenderOpts),
+    #[command(about = "Group rows and aggregate each group")]
+    Group(CsvGroupOpts),
+    #[command(about = "Cross-tabulate one column against others")]
+    Pivot(CsvPivotOpts),
+}
+
+#[derive(Debug, Args)]
+pub struct CsvConvertOpts {
+    // 有子命令时不要求 --input, 因此这里是 Option
+    #[arg(short, long, value_parser = verify_file, required = true)]
+    pub input: Option<String>,
     /*
     default_value 表示这个值需要转换,
     比如"output.json"是一个 &str, 它实现了From<T> trait,
     可以转换为 String, "output.json".into()
      */
+    /// `-` writes to stdout, defaults to output.<format>
+    #[arg(short, long)]
+    pub output: Option<String>,
+    /// Defaults to the extension of --output, or json (csv for non-csv input)
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+    /// Format of the input, anything other than csv is converted to csv.
+    /// Defaults to the extension of --input, or csv
+    #[arg(long, value_parser = parse_input_format)]
+    pub from: Option<InputFormat>,
+    /// Fail instead of overwriting an existing output file
+    #[arg(long)]
+    pub no_clobber: bool,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub types: CsvTypeOpts,
+    #[command(flatten)]
+    pub flatten: CsvFlattenOpts,
+    #[command(flatten)]
+    pub filter: CsvFilterOpts,
+    /// Show rows and bytes processed on stderr
+    #[arg(long)]
+    pub progress: bool,
+}
+
+/// Reshape rows before they are serialized
+#[derive(Debug, Clone, Default, Args)]
+pub struct CsvFilterOpts {
+    /// Only keep these columns, in this order, e.g. "Name,Position"
+    #[arg(long, value_delimiter = ',')]
+    pub select: Vec<String>,
+    /// Only keep rows matching the expression, e.g. "Nationality == 'Italy' && Kit Number > 10"
+    #[arg(long = "where", value_parser = parse_filter)]
+    pub filter: Option<Expr>,
+    /// Sort by columns, e.g. "DOB:desc,Name", this buffers all matching rows
+    #[arg(long, value_parser = parse_sort_key, value_delimiter = ',')]
+    pub sort_by: Vec<SortKey>,
+    /// Stop after N rows
+    #[arg(long)]
+    pub limit: Option<usize>,
+}
+
+/// How nested json/yaml values map to csv columns and back
+#[derive(Debug, Clone, Args)]
+pub struct CsvFlattenOpts {
+    /// What to do with arrays: join them into one cell, explode them into rows,
+    /// or index them into `tags[0]`, `tags[1]` columns
+    #[arg(long, value_parser = parse_array_mode, default_value = "join")]
+    pub arrays: ArrayMode,
+    /// Separator used to join array elements
+    #[arg(long, default_value = ";")]
+    pub array_separator: String,
+    /// Keep dotted and indexed headers (`user.name`, `tags[0]`) as flat keys
+    /// instead of building nested objects and arrays
+    #[arg(long)]
+    pub flat: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArrayMode {
+    Join,
+    Explode,
+    Index,
+}
+
+impl Default for CsvFlattenOpts {
+    fn default() -> Self {
+        Self {
+            arrays: ArrayMode::Join,
+            array_separator: ";".into(),
+            flat: false,
+        }
+    }
+}
+
+/// Where and how rows produced by a subcommand are written
+#[derive(Debug, Clone, Default, Args)]
+pub struct CsvOutputOpts {
+    /// Write to a file instead of stdout, `-` is stdout
     #[arg(short, long)]
     pub output: Option<String>,
-    #[arg(long, value_parser = parse_format, default_value = "json")]
-    pub format: OutputFormat,
-    // default_value_t 表示就是这个字面值, 不需要转换处理
-    #[arg(short, long, default_value_t = ',')]
-    pub delimiter: char,
-    #[arg(long, default_value_t = true)]
+    /// Defaults to the extension of --output, or csv
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+    /// Fail instead of overwriting an existing output file
+    #[arg(long)]
+    pub no_clobber: bool,
+}
+
+/// How the input CSV should be parsed
+#[derive(Debug, Clone, Args)]
+pub struct CsvReaderOpts {
+    /// Field delimiter, use '\t' for TSV
+    #[arg(short, long, value_parser = parse_csv_char, default_value = ",")]
+    pub delimiter: u8,
+    /// Treat the first row as data and name the columns col1, col2, ...
+    #[arg(long = "no-header", action = ArgAction::SetFalse)]
     pub header: bool,
+    /// Quote character
+    #[arg(long, value_parser = parse_csv_char, default_value = "\"")]
+    pub quote: u8,
+    /// Escape character for quotes, by default quotes are escaped by doubling them
+    #[arg(long, value_parser = parse_csv_char)]
+    pub escape: Option<u8>,
+    /// Skip lines starting with this character
+    #[arg(long, value_parser = parse_csv_char)]
+    pub comment: Option<u8>,
+    /// Allow rows with a different number of fields than the header
+    #[arg(long)]
+    pub flexible: bool,
+    /// Sheet to read from an .xlsx/.xls/.ods input, the first one by default
+    #[arg(long)]
+    pub sheet: Option<String>,
+    /// Input encoding, e.g. windows-1252 or utf-16le, "auto" guesses it. A BOM always wins
+    #[arg(long, value_parser = parse_encoding, default_value = "utf-8")]
+    pub encoding: CsvEncoding,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CsvEncoding {
+    Auto,
+    Fixed(&'static Encoding),
+}
+
+/// How cell values are typed in the output
+#[derive(Debug, Clone, Args)]
+pub struct CsvTypeOpts {
+    /// Keep every cell as a string
+    #[arg(long)]
+    pub no_infer: bool,
+    /// Also recognize ISO 8601 / RFC 3339 dates when inferring
+    #[arg(long)]
+    pub infer_dates: bool,
+    /// Override column types, e.g. "Kit Number=int,DOB=string"
+    #[arg(long, value_parser = parse_type_override, value_delimiter = ',')]
+    pub types: Vec<(String, ColumnType)>,
+    /// Number of leading rows used to infer column types; later cells that
+    /// don't fit widen their column, down to string
+    #[arg(long, default_value_t = 1000)]
+    pub infer_rows: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "&'static str")]
+pub enum ColumnType {
+    /// Only empty cells seen so far
+    Null,
+    Boolean,
+    Integer,
+    Float,
+    Date,
+    String,
+}
+
+impl Default for CsvTypeOpts {
+    fn default() -> Self {
+        Self {
+            no_infer: false,
+            infer_dates: false,
+            types: Vec::new(),
+            infer_rows: 1000,
+        }
+    }
+}
+
+impl CsvOutputOpts {
+    /// `--format`, else the extension of `--output`, else csv
+    pub fn format(&self) -> OutputFormat {
+        self.format
+            .or_else(|| self.output.as_deref().and_then(OutputFormat::from_path))
+            .unwrap_or(OutputFormat::Csv)
+    }
+
+    /// Open `--output` for writing, `commit` the file once everything is written
+    pub fn create(&self) -> anyhow::Result<(OutputFile, Box<dyn Write>)> {
+        OutputFile::create(self.output.as_deref(), self.no_clobber)
+    }
+}
+
+impl Default for CsvReaderOpts {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            header: true,
+            quote: b'"',
+            escape: None,
+            comment: None,
+            flexible: false,
+            sheet: None,
+            encoding: CsvEncoding::Fixed(encoding_rs::UTF_8),
+        }
+    }
+}
+
+// csv 只支持单字节的分隔符, 这里同时接受 "\t" 这种转义写法
+fn parse_csv_char(s: &str) -> Result<u8, &'static str> {
+    let c = match s {
+        "\\t" | "tab" => '\t',
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err("Must be a single character"),
+            }
+        }
+    };
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err("Must be an ASCII character")
+    }
 }
 
-fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
+fn parse_input_format(format: &str) -> Result<InputFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_filter(expr: &str) -> Result<Expr, anyhow::Error> {
+    expr.parse()
+}
+
+fn parse_sort_key(key: &str) -> Result<SortKey, anyhow::Error> {
+    key.parse()
+}
+
+fn parse_array_mode(mode: &str) -> Result<ArrayMode, anyhow::Error> {
+    mode.parse()
+}
+
+fn parse_encoding(encoding: &str) -> Result<CsvEncoding, anyhow::Error> {
+    encoding.parse()
+}
+
+// "Kit Number=int" -> ("Kit Number", ColumnType::Integer)
+fn parse_type_override(s: &str) -> Result<(String, ColumnType), anyhow::Error> {
+    let Some((name, ty)) = s.rsplit_once('=') else {
+        return Err(anyhow!("Expect <column>=<type>"));
+    };
+    Ok((name.trim().to_string(), ty.trim().parse()?))
+}
+
+pub(crate) fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
     format.parse()
 }
 
@@ -41,17 +352,38 @@ impl FromStr for OutputFormat {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "json" => Ok(OutputFormat::Json),
-            "yaml" => Ok(OutputFormat::Yaml),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "toml" => Ok(OutputFormat::Toml),
+            "msgpack" | "mpk" => Ok(OutputFormat::Msgpack),
+            "cbor" => Ok(OutputFormat::Cbor),
+            "csv" => Ok(OutputFormat::Csv),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "arrow" | "ipc" | "feather" => Ok(OutputFormat::Arrow),
             _ => Err(anyhow!("Invalid format")),
         }
     }
 }
 
+impl OutputFormat {
+    /// The format a file extension stands for, e.g. `.yml` or `.jsonl`
+    pub fn from_path(path: &str) -> Option<Self> {
+        extension(path)?.parse().ok()
+    }
+}
+
 impl From<OutputFormat> for &'static str {
     fn from(value: OutputFormat) -> Self {
         match value {
             OutputFormat::Json => "json",
             OutputFormat::Yaml => "yaml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Msgpack => "msgpack",
+            OutputFormat::Cbor => "cbor",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
         }
     }
 }
@@ -62,14 +394,274 @@ impl Display for OutputFormat {
     }
 }
 
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(InputFormat::Csv),
+            "json" => Ok(InputFormat::Json),
+            "yaml" | "yml" => Ok(InputFormat::Yaml),
+            "ndjson" | "jsonl" => Ok(InputFormat::Ndjson),
+            _ => Err(anyhow!("Invalid input format")),
+        }
+    }
+}
+
+impl InputFormat {
+    /// json/yaml/ndjson by extension, anything else (spreadsheets too) is csv
+    pub fn from_path(path: &str) -> Self {
+        extension(path)
+            .and_then(|ext| ext.parse().ok())
+            .unwrap_or(InputFormat::Csv)
+    }
+}
+
+fn extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+impl From<InputFormat> for &'static str {
+    fn from(value: InputFormat) -> Self {
+        match value {
+            InputFormat::Csv => "csv",
+            InputFormat::Json => "json",
+            InputFormat::Yaml => "yaml",
+            InputFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl Display for InputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for ArrayMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "join" => Ok(ArrayMode::Join),
+            "explode" => Ok(ArrayMode::Explode),
+            "index" => Ok(ArrayMode::Index),
+            _ => Err(anyhow!("Invalid array mode")),
+        }
+    }
+}
+
+impl From<ArrayMode> for &'static str {
+    fn from(value: ArrayMode) -> Self {
+        match value {
+            ArrayMode::Join => "join",
+            ArrayMode::Explode => "explode",
+            ArrayMode::Index => "index",
+        }
+    }
+}
+
+impl Display for ArrayMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for CsvEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(CsvEncoding::Auto);
+        }
+        // 接受 WHATWG 定义的所有别名, 比如 latin1, cp1252, utf-16
+        Encoding::for_label(s.as_bytes())
+            .map(CsvEncoding::Fixed)
+            .ok_or_else(|| anyhow!("Unknown encoding: {}", s))
+    }
+}
+
+impl From<CsvEncoding> for &'static str {
+    fn from(value: CsvEncoding) -> Self {
+        match value {
+            CsvEncoding::Auto => "auto",
+            CsvEncoding::Fixed(encoding) => encoding.name(),
+        }
+    }
+}
+
+impl Display for CsvEncoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "string" | "str" => Ok(ColumnType::String),
+            "int" | "integer" => Ok(ColumnType::Integer),
+            "float" | "number" => Ok(ColumnType::Float),
+            "bool" | "boolean" => Ok(ColumnType::Boolean),
+            "date" => Ok(ColumnType::Date),
+            _ => Err(anyhow!("Invalid column type")),
+        }
+    }
+}
+
+// 让 schema 文件里的 `type: int` 复用命令行的解析规则
+impl TryFrom<String> for ColumnType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<ColumnType> for &'static str {
+    fn from(value: ColumnType) -> Self {
+        match value {
+            ColumnType::Null => "null",
+            ColumnType::Boolean => "bool",
+            ColumnType::Integer => "int",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        }
+    }
+}
+
+impl Display for ColumnType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
 impl CmdExecutor for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let output = if let Some(output) = self.output {
-            output.clone()
-        } else {
-            format!("output.{}", self.format)
+        match self.cmd {
+            Some(cmd) => cmd.execute().await,
+            None => self.convert.execute().await,
+        }
+    }
+}
+
+impl CmdExecutor for CsvConvertOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let input = self.input.ok_or_else(|| anyhow!("Missing --input"))?;
+        let from = self.from.unwrap_or_else(|| InputFormat::from_path(&input));
+        let format = self
+            .format
+            .or_else(|| self.output.as_deref().and_then(OutputFormat::from_path))
+            .unwrap_or(match from {
+                InputFormat::Csv => OutputFormat::Json,
+                _ => OutputFormat::Csv,
+            });
+        let output = self.output.unwrap_or_else(|| format!("output.{}", format));
+        let out = CsvOutputOpts {
+            output: Some(output),
+            format: Some(format),
+            no_clobber: self.no_clobber,
         };
-        process_csv(&self.input, output, self.format)?;
+        if from == InputFormat::Csv {
+            process_csv(
+                &input,
+                &out,
+                &self.reader,
+                &self.types,
+                &self.flatten,
+                &self.filter,
+                self.progress,
+            )?;
+        } else {
+            if !matches!(format, OutputFormat::Csv) {
+                return Err(anyhow!(
+                    "{} input can only be converted to csv, use --format csv",
+                    from
+                ));
+            }
+            process_to_csv(&input, &out, from, &self.reader, &self.flatten)?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Opts, SubCommand};
+
+    #[test]
+    fn test_csv_opts_parse() {
+        let opts =
+            Opts::try_parse_from(["rcli", "csv", "-i", "Cargo.toml", "--no-header"]).unwrap();
+        let SubCommand::Csv(csv) = opts.cmd else {
+            panic!("expect csv");
+        };
+        assert_eq!(csv.convert.input.as_deref(), Some("Cargo.toml"));
+        assert!(!csv.convert.reader.header);
+
+        let opts = Opts::try_parse_from(["rcli", "csv", "show", "-i", "Cargo.toml"]).unwrap();
+        let SubCommand::Csv(csv) = opts.cmd else {
+            panic!("expect csv");
+        };
+        assert!(matches!(csv.cmd, Some(CsvSubCommand::Show(_))));
+
+        assert!(Opts::try_parse_from(["rcli", "csv"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_csv_char() {
+        assert_eq!(parse_csv_char(","), Ok(b','));
+        assert_eq!(parse_csv_char(";"), Ok(b';'));
+        assert_eq!(parse_csv_char("\\t"), Ok(b'\t'));
+        assert_eq!(parse_csv_char("\t"), Ok(b'\t'));
+        assert_eq!(parse_csv_char(",,"), Err("Must be a single character"));
+        assert_eq!(parse_csv_char("§"), Err("Must be an ASCII character"));
+    }
+
+    #[test]
+    fn test_output_format_round_trip() {
+        for name in ["json", "yaml", "ndjson", "toml", "msgpack", "cbor", "csv"] {
+            let format: OutputFormat = name.parse().unwrap();
+            assert_eq!(format.to_string(), name);
+        }
+        assert!(matches!("jsonl".parse(), Ok(OutputFormat::Ndjson)));
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_formats_from_extension() {
+        assert!(matches!(
+            OutputFormat::from_path("out/players.YML"),
+            Some(OutputFormat::Yaml)
+        ));
+        assert!(OutputFormat::from_path("players.txt").is_none());
+        assert!(OutputFormat::from_path("-").is_none());
+        assert_eq!(InputFormat::from_path("rows.jsonl"), InputFormat::Ndjson);
+        assert_eq!(InputFormat::from_path("players.xlsx"), InputFormat::Csv);
+
+        let out = CsvOutputOpts {
+            output: Some("players.ndjson".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(out.format(), OutputFormat::Ndjson));
+        assert!(matches!(
+            CsvOutputOpts::default().format(),
+            OutputFormat::Csv
+        ));
+    }
+
+    #[test]
+    fn test_parse_type_override() {
+        let (name, ty) = parse_type_override("Kit Number=int").unwrap();
+        assert_eq!(name, "Kit Number");
+        assert_eq!(ty, ColumnType::Integer);
+        assert!(parse_type_override("DOB").is_err());
+        assert!(parse_type_override("DOB=datetime").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use super::verify_file;
+use crate::{diff_csv, write_diff, CmdExecutor, CsvReaderOpts, OutputFile, DEFAULT_SORT_BUFFER};
+use anyhow::anyhow;
+use clap::Parser;
+use std::fmt::{Display, Formatter};
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiffFormat {
+    /// Colored `+`/`-`/`~` lines with before/after values
+    Text,
+    /// A json array of changes
+    Json,
+    /// Csv with an `_op` column (add/remove/change) followed by the row
+    Patch,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDiffOpts {
+    #[arg(value_parser = verify_file)]
+    pub old: String,
+    #[arg(value_parser = verify_file)]
+    pub new: String,
+    /// Columns identifying a row, e.g. --key Name or --key "Name,DOB"
+    #[arg(short, long, value_delimiter = ',', required = true)]
+    pub key: Vec<String>,
+    #[arg(long, value_parser = parse_diff_format, default_value = "text")]
+    pub format: DiffFormat,
+    /// Write the diff to a file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+    /// Fail instead of overwriting an existing output file
+    #[arg(long)]
+    pub no_clobber: bool,
+    /// Rows sorted in memory at a time, larger files are sorted on disk
+    #[arg(long, default_value_t = DEFAULT_SORT_BUFFER)]
+    pub sort_buffer: usize,
+    #[arg(long)]
+    pub no_color: bool,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let diff = diff_csv(
+            &self.old,
+            &self.new,
+            &self.key,
+            &self.reader,
+            self.sort_buffer,
+        )?;
+        match &self.output {
+            Some(output) => {
+                let (file, writer) = OutputFile::create(Some(output), self.no_clobber)?;
+                write_diff(diff, writer, self.format, false)?;
+                file.commit()?;
+            }
+            None => {
+                let color = !self.no_color && io::stdout().is_terminal();
+                write_diff(diff, io::stdout().lock(), self.format, color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_diff_format(format: &str) -> Result<DiffFormat, anyhow::Error> {
+    format.parse()
+}
+
+impl FromStr for DiffFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(DiffFormat::Text),
+            "json" => Ok(DiffFormat::Json),
+            "patch" | "csv" => Ok(DiffFormat::Patch),
+            _ => Err(anyhow!("Invalid diff format")),
+        }
+    }
+}
+
+impl From<DiffFormat> for &'static str {
+    fn from(value: DiffFormat) -> Self {
+        match value {
+            DiffFormat::Text => "text",
+            DiffFormat::Json => "json",
+            DiffFormat::Patch => "patch",
+        }
+    }
+}
+
+impl Display for DiffFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
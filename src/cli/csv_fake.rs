@@ -0,0 +1,25 @@
+use super::verify_file;
+use crate::{process_csv_fake, CmdExecutor, CsvOutputOpts, CsvSchema};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct CsvFakeOpts {
+    /// Yaml schema, the same one `rcli csv validate` reads
+    #[arg(short, long, value_parser = verify_file)]
+    pub schema: String,
+    /// Number of rows to generate
+    #[arg(long, default_value_t = 100)]
+    pub rows: usize,
+    /// Seed the random generator for reproducible rows
+    #[arg(long)]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+impl CmdExecutor for CsvFakeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let schema = CsvSchema::load(&self.schema)?;
+        process_csv_fake(&schema, self.rows, self.seed, &self.out)
+    }
+}
@@ -0,0 +1,76 @@
+use super::verify_file;
+use crate::{
+    process_csv_group, process_csv_pivot, Aggregate, CmdExecutor, CsvOutputOpts, CsvReaderOpts,
+    CsvTypeOpts,
+};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct CsvGroupOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Columns to group by, e.g. "Position,Nationality"; empty means one group
+    #[arg(long, value_delimiter = ',')]
+    pub by: Vec<String>,
+    /// Aggregates, e.g. "count(*),min(DOB),avg(Kit Number)"; count, sum, min, max, avg
+    #[arg(long, value_parser = parse_aggregate, value_delimiter = ',', required = true)]
+    pub agg: Vec<Aggregate>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub types: CsvTypeOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvPivotOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Columns whose values become the rows
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub rows: Vec<String>,
+    /// Column whose values become the columns
+    #[arg(long)]
+    pub cols: String,
+    /// Aggregate in each cell, e.g. "count" or "sum(Kit Number)"
+    #[arg(long, value_parser = parse_aggregate, default_value = "count")]
+    pub value: Aggregate,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub types: CsvTypeOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+fn parse_aggregate(s: &str) -> Result<Aggregate, anyhow::Error> {
+    s.parse()
+}
+
+impl CmdExecutor for CsvGroupOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_group(
+            &self.input,
+            &self.by,
+            &self.agg,
+            &self.reader,
+            &self.types,
+            &self.out,
+        )
+    }
+}
+
+impl CmdExecutor for CsvPivotOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_pivot(
+            &self.input,
+            &self.rows,
+            &self.cols,
+            &self.value,
+            &self.reader,
+            &self.types,
+            &self.out,
+        )
+    }
+}
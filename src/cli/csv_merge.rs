@@ -0,0 +1,124 @@
+use super::verify_file;
+use crate::{
+    process_csv_cat, process_csv_dedup, process_csv_join, CmdExecutor, CsvOutputOpts,
+    CsvReaderOpts, DEFAULT_SORT_BUFFER,
+};
+use anyhow::anyhow;
+use clap::Parser;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JoinType {
+    /// Only rows with a match on both sides
+    Inner,
+    /// Every left row, right columns are empty without a match
+    Left,
+    /// Every row from both sides
+    Outer,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvJoinOpts {
+    #[arg(value_parser = verify_file)]
+    pub left: String,
+    #[arg(value_parser = verify_file)]
+    pub right: String,
+    /// Columns both files are joined on, e.g. --on id or --on "Name,DOB"
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub on: Vec<String>,
+    #[arg(long, value_parser = parse_join_type, default_value = "inner")]
+    pub how: JoinType,
+    /// Rows sorted in memory at a time, larger files are sorted on disk
+    #[arg(long, default_value_t = DEFAULT_SORT_BUFFER)]
+    pub sort_buffer: usize,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvCatOpts {
+    #[arg(value_parser = verify_file, required = true)]
+    pub inputs: Vec<String>,
+    /// Only keep columns present in every file instead of all of them
+    #[arg(long)]
+    pub intersect: bool,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDedupOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Columns that identify a duplicate, the whole row when omitted
+    #[arg(long, value_delimiter = ',')]
+    pub by: Vec<String>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+impl CmdExecutor for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_join(
+            &self.left,
+            &self.right,
+            &self.on,
+            self.how,
+            self.sort_buffer,
+            &self.reader,
+            &self.out,
+        )
+    }
+}
+
+impl CmdExecutor for CsvCatOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_cat(&self.inputs, self.intersect, &self.reader, &self.out)
+    }
+}
+
+impl CmdExecutor for CsvDedupOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_dedup(&self.input, &self.by, &self.reader, &self.out)
+    }
+}
+
+fn parse_join_type(how: &str) -> Result<JoinType, anyhow::Error> {
+    how.parse()
+}
+
+impl FromStr for JoinType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inner" => Ok(JoinType::Inner),
+            "left" => Ok(JoinType::Left),
+            "outer" | "full" => Ok(JoinType::Outer),
+            _ => Err(anyhow!("Invalid join type")),
+        }
+    }
+}
+
+impl From<JoinType> for &'static str {
+    fn from(value: JoinType) -> Self {
+        match value {
+            JoinType::Inner => "inner",
+            JoinType::Left => "left",
+            JoinType::Outer => "outer",
+        }
+    }
+}
+
+impl Display for JoinType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -0,0 +1,53 @@
+use super::csv::parse_format;
+use super::verify_file;
+use crate::{
+    process_csv_query, write_query_result, CmdExecutor, CsvReaderOpts, CsvTypeOpts, OutputFile,
+    OutputFormat,
+};
+use clap::Parser;
+use std::io;
+
+#[derive(Debug, Parser)]
+pub struct CsvQueryOpts {
+    /// SQL to run, each input is a table named after its file stem
+    pub sql: String,
+    /// Input file, repeat to load several tables
+    #[arg(short, long = "input", value_parser = verify_file, required = true)]
+    pub inputs: Vec<String>,
+    /// Write the result to a file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+    /// Output format, the result is shown as a table when omitted
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+    /// Fail instead of overwriting an existing output file
+    #[arg(long)]
+    pub no_clobber: bool,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub types: CsvTypeOpts,
+}
+
+impl CmdExecutor for CsvQueryOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let result = process_csv_query(&self.sql, &self.inputs, &self.reader, &self.types)?;
+        match &self.output {
+            Some(output) => {
+                let (file, writer) = OutputFile::create(Some(output), self.no_clobber)?;
+                write_query_result(writer, self.format, result, &self.reader, None)?;
+                file.commit()
+            }
+            None => {
+                let width = terminal_size::terminal_size().map(|(w, _)| w.0 as usize);
+                write_query_result(
+                    io::stdout().lock(),
+                    self.format,
+                    result,
+                    &self.reader,
+                    width,
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use super::verify_file;
+use crate::{process_csv_render, CmdExecutor, CsvReaderOpts, CsvTypeOpts};
+use clap::{Args, Parser};
+
+#[derive(Debug, Parser)]
+pub struct CsvRenderOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Minijinja template, rendered once per row with the columns as variables
+    #[arg(short, long, value_parser = verify_file)]
+    pub template: String,
+    /// Render the template once with `rows` and `headers` instead of once per row
+    #[arg(long, conflicts_with = "filename")]
+    pub all: bool,
+    #[command(flatten)]
+    pub out: CsvRenderOutputOpts,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub types: CsvTypeOpts,
+}
+
+/// Where rendered text goes, stdout by default
+#[derive(Debug, Clone, Default, Args)]
+pub struct CsvRenderOutputOpts {
+    /// Write to a file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+    /// Write every row to its own file, named by this template, e.g. "players/{{ Name | slug }}.toml"
+    #[arg(long, conflicts_with = "output")]
+    pub filename: Option<String>,
+    /// Directory the --filename paths are relative to, rendered paths can't leave it
+    #[arg(long, default_value = ".", requires = "filename")]
+    pub out_dir: String,
+    /// Fail instead of overwriting an existing output file
+    #[arg(long)]
+    pub no_clobber: bool,
+}
+
+impl CmdExecutor for CsvRenderOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let files = process_csv_render(
+            &self.input,
+            &self.template,
+            self.all,
+            &self.out,
+            &self.reader,
+            &self.types,
+        )?;
+        for file in files {
+            println!("{}", file.display());
+        }
+        Ok(())
+    }
+}
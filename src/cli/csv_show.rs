@@ -0,0 +1,41 @@
+use super::verify_file;
+use crate::{process_csv_show, CmdExecutor, CsvReaderOpts};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct CsvShowOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Only show the first N rows (after --offset)
+    #[arg(long, conflicts_with = "tail")]
+    pub head: Option<usize>,
+    /// Only show the last N rows
+    #[arg(long)]
+    pub tail: Option<usize>,
+    /// Skip the first N rows
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+    /// Maximum table width, defaults to the terminal width
+    #[arg(long)]
+    pub width: Option<usize>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvShowOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let width = self
+            .width
+            .or_else(|| terminal_size::terminal_size().map(|(w, _)| w.0 as usize));
+        let table = process_csv_show(
+            &self.input,
+            &self.reader,
+            self.offset,
+            self.head,
+            self.tail,
+            width,
+        )?;
+        print!("{}", table);
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use super::verify_file;
+use crate::{
+    process_csv_head, process_csv_sample, process_csv_split_by, process_csv_split_rows,
+    process_csv_tail, CmdExecutor, CsvOutputOpts, CsvReaderOpts,
+};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct CsvSplitOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Rows per output file
+    #[arg(
+        long,
+        conflicts_with = "by_column",
+        required_unless_present = "by_column"
+    )]
+    pub rows: Option<usize>,
+    /// Write one file per distinct value of this column
+    #[arg(long)]
+    pub by_column: Option<String>,
+    /// Directory the files are written to
+    #[arg(long, default_value = ".")]
+    pub out_dir: String,
+    /// Fail instead of overwriting an existing output file
+    #[arg(long)]
+    pub no_clobber: bool,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvSampleOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Number of rows to sample
+    #[arg(short, long)]
+    pub n: usize,
+    /// Seed the random generator for a reproducible sample
+    #[arg(long)]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvHeadOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Number of rows
+    #[arg(short, long, default_value_t = 10)]
+    pub n: usize,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvTailOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Number of rows
+    #[arg(short, long, default_value_t = 10)]
+    pub n: usize,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+impl CmdExecutor for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let files = match (self.rows, &self.by_column) {
+            (_, Some(column)) => process_csv_split_by(
+                &self.input,
+                column,
+                &self.out_dir,
+                self.no_clobber,
+                &self.reader,
+            )?,
+            (rows, None) => process_csv_split_rows(
+                &self.input,
+                rows.unwrap_or_default(),
+                &self.out_dir,
+                self.no_clobber,
+                &self.reader,
+            )?,
+        };
+        for file in files {
+            println!("{}", file.display());
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvSampleOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_sample(&self.input, self.n, self.seed, &self.reader, &self.out)
+    }
+}
+
+impl CmdExecutor for CsvHeadOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_head(&self.input, self.n, &self.reader, &self.out)
+    }
+}
+
+impl CmdExecutor for CsvTailOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_tail(&self.input, self.n, &self.reader, &self.out)
+    }
+}
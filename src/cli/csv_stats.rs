@@ -0,0 +1,33 @@
+use super::verify_file;
+use crate::{process_csv_stats, render_stats, CmdExecutor, CsvReaderOpts};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct CsvStatsOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Number of most frequent values to report per column
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+    /// Estimate distinct counts with HyperLogLog, for files with many distinct values
+    #[arg(long)]
+    pub approx: bool,
+    /// Print the stats as json instead of a table
+    #[arg(long)]
+    pub json: bool,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let stats = process_csv_stats(&self.input, &self.reader, self.top, self.approx)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+        } else {
+            let width = terminal_size::terminal_size().map(|(w, _)| w.0 as usize);
+            print!("{}", render_stats(&stats, width));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use super::verify_file;
+use crate::{process_csv_validate, CmdExecutor, CsvReaderOpts, CsvSchema};
+use anyhow::anyhow;
+use clap::Parser;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// One `line:column: message` per error
+    Text,
+    /// A json array of errors
+    Json,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Yaml file declaring the columns
+    #[arg(short, long, value_parser = verify_file)]
+    pub schema: String,
+    #[arg(long, value_parser = parse_report_format, default_value = "text")]
+    pub report: ReportFormat,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let schema = CsvSchema::load(&self.schema)?;
+        let errors = process_csv_validate(&self.input, &schema, &self.reader)?;
+        match self.report {
+            ReportFormat::Text => {
+                for error in &errors {
+                    println!("{}", error);
+                }
+            }
+            ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&errors)?),
+        }
+        // 有错误时以非零状态退出, 方便在流水线里当作检查关卡
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("{} validation error(s)", errors.len()))
+        }
+    }
+}
+
+fn parse_report_format(format: &str) -> Result<ReportFormat, anyhow::Error> {
+    format.parse()
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(anyhow!("Invalid report format")),
+        }
+    }
+}
+
+impl From<ReportFormat> for &'static str {
+    fn from(value: ReportFormat) -> Self {
+        match value {
+            ReportFormat::Text => "text",
+            ReportFormat::Json => "json",
+        }
+    }
+}
+
+impl Display for ReportFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
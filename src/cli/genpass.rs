@@ -1,34 +1,261 @@
-use crate::{process_genpass, CmdExecutor};
-use clap::Parser;
-use zxcvbn::zxcvbn;
+use super::verify_file;
+use crate::{
+    estimate_strength, generate_passphrase, load_wordlist, policy_entropy, process_genpass,
+    process_genpass_batch, process_genpass_derive, read_master, CmdExecutor, GeneratedPassword,
+    DEFAULT_SYMBOLS, MAX_COUNT,
+};
+use clap::builder::RangedU64ValueParser;
+use clap::{ArgAction, Args, Parser, Subcommand};
+use enum_dispatch::enum_dispatch;
+use serde::Deserialize;
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct GenPassOpts {
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+    /// Generate a diceware passphrase instead of a random string
+    #[arg(long, conflicts_with_all = [
+        "length", "uppercase", "lowercase", "numbers", "symbols", "symbol_set",
+        "min_uppercase", "min_lowercase", "min_numbers", "min_symbols",
+        "exclude", "no_repeat", "no_sequence",
+    ])]
+    pub passphrase: bool,
+    /// Number of passwords to generate
+    #[arg(
+        short,
+        long,
+        default_value_t = 1,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..=MAX_COUNT as u64)
+    )]
+    pub count: usize,
+    /// Print a json array with the strength report of every password
+    #[arg(long)]
+    pub json: bool,
+    /// Regenerate until zxcvbn scores the password at least this (0-4)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: Option<u8>,
+    #[command(flatten)]
+    pub policy: PasswordPolicy,
+    #[command(flatten)]
+    pub phrase: PassphraseOpts,
+}
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenPassSubCommand {
+    #[command(about = "Derive a site password from a master passphrase, nothing is stored")]
+    Derive(GenPassDeriveOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct GenPassDeriveOpts {
+    /// Site the password is for, e.g. example.com (case-insensitive)
+    #[arg(long)]
+    pub site: String,
+    /// Account name on the site
+    #[arg(long, default_value = "")]
+    pub login: String,
+    /// Bump to rotate the password
+    #[arg(long, default_value_t = 1)]
+    pub counter: u32,
+    /// Read the master passphrase from the first line of this file, `-` for
+    /// stdin. Prompts without echo when omitted
+    #[arg(long, value_parser = verify_file)]
+    pub master_file: Option<String>,
+    #[command(flatten)]
+    pub policy: PasswordPolicy,
+}
+
+/// Words making up a `--passphrase`
+#[derive(Debug, Clone, Args)]
+pub struct PassphraseOpts {
+    /// Number of words
+    #[arg(long, default_value_t = 6, requires = "passphrase")]
+    pub words: usize,
+    /// Put between words
+    #[arg(
+        long,
+        default_value = "-",
+        allow_hyphen_values = true,
+        requires = "passphrase"
+    )]
+    pub separator: String,
+    /// Capitalize every word
+    #[arg(long, requires = "passphrase")]
+    pub capitalize: bool,
+    /// Append a random digit to one of the words
+    #[arg(long, requires = "passphrase")]
+    pub add_digit: bool,
+    /// One word per line, EFF dice lists work as downloaded. Defaults to the
+    /// built-in BIP-39 English list (2048 words)
+    #[arg(long, value_parser = verify_file, requires = "passphrase")]
+    pub wordlist: Option<String>,
+}
+
+/// What a generated password must look like
+#[derive(Debug, Clone, Args, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
     #[arg(short, long, default_value_t = 16)]
-    pub length: u8,
-    #[arg(long, default_value_t = true)]
+    pub length: usize,
+    /// Leave out uppercase letters
+    #[arg(long = "no-uppercase", action = ArgAction::SetFalse)]
     pub uppercase: bool,
-    #[arg(long, default_value_t = true)]
+    /// Leave out lowercase letters
+    #[arg(long = "no-lowercase", action = ArgAction::SetFalse)]
     pub lowercase: bool,
-    #[arg(long, default_value_t = true)]
+    /// Leave out digits
+    #[arg(long = "no-numbers", action = ArgAction::SetFalse)]
     pub numbers: bool,
-    #[arg(long, default_value_t = true)]
-    pub symbol: bool,
+    /// Leave out symbols
+    #[arg(long = "no-symbols", action = ArgAction::SetFalse)]
+    pub symbols: bool,
+    /// Characters used as symbols
+    #[arg(long, default_value = DEFAULT_SYMBOLS, allow_hyphen_values = true)]
+    pub symbol_set: String,
+    /// At least this many uppercase letters, 1 by default
+    #[arg(long)]
+    pub min_uppercase: Option<usize>,
+    /// At least this many lowercase letters, 1 by default
+    #[arg(long)]
+    pub min_lowercase: Option<usize>,
+    /// At least this many digits, 1 by default
+    #[arg(long)]
+    pub min_numbers: Option<usize>,
+    /// At least this many symbols, 1 by default
+    #[arg(long)]
+    pub min_symbols: Option<usize>,
+    /// Characters that must never appear, e.g. "0O1lI"
+    #[arg(long, default_value = "", allow_hyphen_values = true)]
+    pub exclude: String,
+    /// Use every character at most once
+    #[arg(long)]
+    pub no_repeat: bool,
+    /// No runs of three consecutive characters such as "abc" or "321"
+    #[arg(long)]
+    pub no_sequence: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            length: 16,
+            uppercase: true,
+            lowercase: true,
+            numbers: true,
+            symbols: true,
+            symbol_set: DEFAULT_SYMBOLS.to_string(),
+            min_uppercase: None,
+            min_lowercase: None,
+            min_numbers: None,
+            min_symbols: None,
+            exclude: String::new(),
+            no_repeat: false,
+            no_sequence: false,
+        }
+    }
 }
 
 impl CmdExecutor for GenPassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let password = process_genpass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.numbers,
-            self.symbol,
-        )?;
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        // json 要输出一个完整的数组, 先收集; 文本模式生成一个输出一个
+        let mut passwords = Vec::new();
+        let json = self.json;
+        let emit = |generated: GeneratedPassword| {
+            if json {
+                passwords.push(generated);
+            } else {
+                println!("{}", generated.password);
+                // Output password strength in stderr
+                eprintln!(
+                    "Entropy: {:.2} bits, password strength: {}",
+                    generated.entropy, generated.strength.score
+                );
+            }
+            Ok(())
+        };
+        if self.passphrase {
+            let words = load_wordlist(self.phrase.wordlist.as_deref())?;
+            let mut rng = rand::thread_rng();
+            let generate = || {
+                let passphrase = generate_passphrase(&words, &self.phrase, &mut rng)?;
+                Ok((passphrase.phrase, passphrase.entropy))
+            };
+            process_genpass_batch(self.count, self.min_score, generate, emit)?;
+        } else {
+            let entropy = policy_entropy(&self.policy)?;
+            let generate = || Ok((process_genpass(&self.policy)?, entropy));
+            process_genpass_batch(self.count, self.min_score, generate, emit)?;
+        }
+        if json {
+            println!("{}", serde_json::to_string_pretty(&passwords)?);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for GenPassDeriveOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let master = read_master(self.master_file.as_deref())?;
+        let password =
+            process_genpass_derive(&master, &self.site, &self.login, self.counter, &self.policy)?;
         println!("{}", password);
-        // Output password strength in stderr
-        let estimate = zxcvbn(&password, &[])?;
-        eprintln!("Password strength: {}", estimate.score());
+        let strength = estimate_strength(&password, &[&self.site, &self.login])?;
+        eprintln!("Password strength: {}", strength.score);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Opts, SubCommand};
+
+    #[test]
+    fn test_genpass_negations() {
+        let opts = Opts::try_parse_from(["rcli", "genpass", "--no-symbols", "-l", "300"]).unwrap();
+        let SubCommand::GenPass(opts) = opts.cmd else {
+            panic!("expect genpass");
+        };
+        assert!(!opts.policy.symbols);
+        assert!(opts.policy.uppercase);
+        assert_eq!(opts.policy.length, 300);
+    }
+
+    #[test]
+    fn test_passphrase_conflicts_with_policy() {
+        for flag in ["--no-symbols", "--exclude=0O", "--min-numbers=2"] {
+            assert!(Opts::try_parse_from(["rcli", "genpass", "--passphrase", flag]).is_err());
+        }
+        assert!(Opts::try_parse_from(["rcli", "genpass", "-l", "20", "--passphrase"]).is_err());
+        assert!(Opts::try_parse_from(["rcli", "genpass", "--words", "8"]).is_err());
+        assert!(Opts::try_parse_from(["rcli", "genpass", "--passphrase", "--words", "8"]).is_ok());
+    }
+
+    #[test]
+    fn test_genpass_derive_args() {
+        let opts = Opts::try_parse_from([
+            "rcli",
+            "genpass",
+            "derive",
+            "--site",
+            "example.com",
+            "--no-symbols",
+        ])
+        .unwrap();
+        let SubCommand::GenPass(GenPassOpts {
+            cmd: Some(GenPassSubCommand::Derive(opts)),
+            ..
+        }) = opts.cmd
+        else {
+            panic!("expect genpass derive");
+        };
+        assert_eq!(opts.counter, 1);
+        assert!(!opts.policy.symbols);
+        assert!(Opts::try_parse_from(["rcli", "genpass", "-l", "20", "derive"]).is_err());
+    }
+}
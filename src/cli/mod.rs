@@ -1,11 +1,26 @@
 mod base64;
 mod csv;
+mod csv_diff;
+mod csv_fake;
+mod csv_group;
+mod csv_merge;
+mod csv_query;
+mod csv_render;
+mod csv_show;
+mod csv_split;
+mod csv_stats;
+mod csv_validate;
 mod genpass;
 mod http;
 mod jwt;
+mod password;
 mod text;
 
-pub use self::{base64::*, csv::*, genpass::*, http::*, jwt::*, text::*};
+pub use self::{
+    base64::*, csv::*, csv_diff::*, csv_fake::*, csv_group::*, csv_merge::*, csv_query::*,
+    csv_render::*, csv_show::*, csv_split::*, csv_stats::*, csv_validate::*, genpass::*, http::*,
+    jwt::*, password::*, text::*,
+};
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 use regex::Regex;
@@ -20,6 +35,8 @@ pub struct Opts {
     pub cmd: SubCommand,
 }
 
+// 只在启动时构造一次, 变体大小不一致没有关系
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
 pub enum SubCommand {
@@ -35,6 +52,8 @@ pub enum SubCommand {
     Http(HttpSubCommand),
     #[command(subcommand, about = "JWT sign/verify")]
     JWT(JWTSubCommand),
+    #[command(subcommand, about = "Password strength audit")]
+    Password(PasswordSubCommand),
 }
 
 fn verify_file(file_name: &str) -> Result<String, &'static str> {
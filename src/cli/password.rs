@@ -0,0 +1,108 @@
+use super::verify_file;
+use crate::{process_password_check, read_passwords, CmdExecutor, PasswordCheck};
+use anyhow::anyhow;
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum PasswordSubCommand {
+    #[command(about = "Audit password strength and look passwords up in a breach list")]
+    Check(PasswordCheckOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct PasswordCheckOpts {
+    /// One password per line, `-` for stdin. Prompts without echo when omitted
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: Option<String>,
+    /// Words the password shouldn't be built from, e.g. "alice,alice@example.com"
+    #[arg(long, value_delimiter = ',')]
+    pub user_input: Vec<String>,
+    /// HIBP-style SHA-1 list of "HASH:COUNT" lines, or a directory of range
+    /// files named by their 5 character prefix
+    #[arg(long, value_parser = verify_file)]
+    pub breach_file: Option<String>,
+    /// Exit with an error when a password scores below this (0-4) or is breached
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: u8,
+    /// Print a json array instead of a text report
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl CmdExecutor for PasswordCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let passwords = read_passwords(self.input.as_deref())?;
+        // 空输入不能算作检查通过
+        if passwords.is_empty() {
+            return Err(anyhow!("No passwords to check, the input is empty"));
+        }
+        let checks = process_password_check(
+            &passwords,
+            &self.user_input,
+            self.breach_file.as_deref(),
+            self.min_score,
+        )?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&checks)?);
+        } else {
+            for check in &checks {
+                print_check(check);
+            }
+        }
+        let failed = checks.iter().filter(|c| !c.passed).count();
+        if failed > 0 {
+            return Err(anyhow!(
+                "{} of {} passwords failed (minimum score {}, not breached)",
+                failed,
+                checks.len(),
+                self.min_score
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn print_check(check: &PasswordCheck) {
+    let s = &check.strength;
+    let breached = match check.breached {
+        Some(0) => ", not breached".to_string(),
+        Some(n) => format!(", found {} times in breaches", n),
+        None => String::new(),
+    };
+    println!(
+        "line {}: {}, score {}/4, 10^{:.1} guesses{}",
+        check.line,
+        if check.passed { "ok" } else { "FAIL" },
+        s.score,
+        s.guesses_log10,
+        breached
+    );
+    let t = &s.crack_times;
+    println!(
+        "  crack time: {} online (throttled), {} online, {} offline (slow hash), {} offline (fast hash)",
+        t.online_throttling_100_per_hour.display,
+        t.online_no_throttling_10_per_second.display,
+        t.offline_slow_hashing_1e4_per_second.display,
+        t.offline_fast_hashing_1e10_per_second.display
+    );
+    for m in &s.sequence {
+        match m.detail.as_str() {
+            "" => println!("  chars {}-{}: {}", m.start + 1, m.end + 1, m.pattern),
+            detail => println!(
+                "  chars {}-{}: {} ({})",
+                m.start + 1,
+                m.end + 1,
+                m.pattern,
+                detail
+            ),
+        }
+    }
+    if let Some(warning) = &s.feedback.warning {
+        println!("  warning: {}", warning);
+    }
+    for suggestion in &s.feedback.suggestions {
+        println!("  suggestion: {}", suggestion);
+    }
+}
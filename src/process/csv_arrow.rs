@@ -0,0 +1,313 @@
+use super::csv_writer::RowWriter;
+use crate::OutputFormat;
+use anyhow::{anyhow, Result};
+use arrow_array::builder::{BooleanBuilder, Float64Builder, Int64Builder, StringBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+/// Rows per record batch
+const BATCH_ROWS: usize = 8192;
+
+/// Write rows as parquet or an arrow ipc file.
+///
+/// Column types are merged over every row: ints, floats (ints widen to
+/// floats), bools, and strings for everything else or for mixed columns.
+/// Rows are spilled to a temporary file until the types are known, so
+/// memory stays constant.
+pub struct ArrowRowWriter<W: Write> {
+    format: OutputFormat,
+    headers: Vec<String>,
+    types: Vec<Option<DataType>>,
+    spill: BufWriter<File>,
+    writer: W,
+}
+
+enum Sink<W: Write> {
+    // ArrowWriter 要求 Send, 先写到临时文件, 结束时再拷贝到输出
+    Parquet(Box<ArrowWriter<File>>, W),
+    Arrow(FileWriter<W>),
+}
+
+impl<W: Write> ArrowRowWriter<W> {
+    pub fn new(writer: W, format: OutputFormat, headers: &[String]) -> Result<Self> {
+        Ok(Self {
+            format,
+            headers: headers.to_vec(),
+            types: vec![None; headers.len()],
+            spill: BufWriter::new(tempfile::tempfile()?),
+            writer,
+        })
+    }
+
+    fn schema(&self) -> SchemaRef {
+        let fields: Vec<Field> = self
+            .headers
+            .iter()
+            .zip(&self.types)
+            .map(|(name, ty)| Field::new(name, ty.clone().unwrap_or(DataType::Utf8), true))
+            .collect();
+        Arc::new(Schema::new(fields))
+    }
+}
+
+impl<W: Write> RowWriter for ArrowRowWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        for (name, ty) in self.headers.iter().zip(self.types.iter_mut()) {
+            if let Some(t) = row.get(name).and_then(value_type) {
+                *ty = Some(match ty.take() {
+                    None => t,
+                    Some(current) => merge_type(current, t),
+                });
+            }
+        }
+        serde_json::to_writer(&mut self.spill, row)?;
+        self.spill.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        let schema = self.schema();
+        let Self {
+            format,
+            spill,
+            writer,
+            ..
+        } = *self;
+        let mut spill = spill.into_inner().map_err(|e| anyhow!("{}", e))?;
+        spill.seek(SeekFrom::Start(0))?;
+
+        let mut sink = match format {
+            OutputFormat::Parquet => Sink::Parquet(
+                Box::new(ArrowWriter::try_new(
+                    tempfile::tempfile()?,
+                    schema.clone(),
+                    None,
+                )?),
+                writer,
+            ),
+            _ => Sink::Arrow(FileWriter::try_new(writer, &schema)?),
+        };
+        let mut rows = Vec::with_capacity(BATCH_ROWS);
+        for line in BufReader::new(spill).lines() {
+            rows.push(serde_json::from_str(&line?)?);
+            if rows.len() >= BATCH_ROWS {
+                sink.write(&build_batch(&schema, &rows)?)?;
+                rows.clear();
+            }
+        }
+        if !rows.is_empty() {
+            sink.write(&build_batch(&schema, &rows)?)?;
+        }
+
+        match sink {
+            Sink::Parquet(w, mut writer) => {
+                // into_inner 会写入文件尾部的元数据
+                let mut file = w.into_inner()?;
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(&mut file, &mut writer)?;
+                writer.flush()?;
+            }
+            Sink::Arrow(mut w) => {
+                w.finish()?;
+                w.get_mut().flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink<W> {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        match self {
+            Sink::Parquet(w, _) => w.write(batch)?,
+            Sink::Arrow(w) => w.write(batch)?,
+        }
+        Ok(())
+    }
+}
+
+fn merge_type(a: DataType, b: DataType) -> DataType {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => {
+            DataType::Float64
+        }
+        _ => DataType::Utf8,
+    }
+}
+
+fn value_type(value: &Value) -> Option<DataType> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some(DataType::Boolean),
+        Value::Number(n) if n.is_i64() => Some(DataType::Int64),
+        Value::Number(_) => Some(DataType::Float64),
+        _ => Some(DataType::Utf8),
+    }
+}
+
+fn build_batch(schema: &SchemaRef, rows: &[Value]) -> Result<RecordBatch> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let name = field.name();
+            let values = rows.iter().map(|row| row.get(name).unwrap_or(&Value::Null));
+            let mismatch = |v: &Value| {
+                anyhow!(
+                    "column {:?}: {} does not match its {} type",
+                    name,
+                    v,
+                    field.data_type()
+                )
+            };
+            let array: ArrayRef = match field.data_type() {
+                DataType::Int64 => {
+                    let mut b = Int64Builder::with_capacity(rows.len());
+                    for v in values {
+                        match v {
+                            Value::Null => b.append_null(),
+                            v => b.append_value(v.as_i64().ok_or_else(|| mismatch(v))?),
+                        }
+                    }
+                    Arc::new(b.finish())
+                }
+                DataType::Float64 => {
+                    let mut b = Float64Builder::with_capacity(rows.len());
+                    for v in values {
+                        match v {
+                            Value::Null => b.append_null(),
+                            v => b.append_value(v.as_f64().ok_or_else(|| mismatch(v))?),
+                        }
+                    }
+                    Arc::new(b.finish())
+                }
+                DataType::Boolean => {
+                    let mut b = BooleanBuilder::with_capacity(rows.len());
+                    for v in values {
+                        match v {
+                            Value::Null => b.append_null(),
+                            v => b.append_value(v.as_bool().ok_or_else(|| mismatch(v))?),
+                        }
+                    }
+                    Arc::new(b.finish())
+                }
+                _ => {
+                    let mut b = StringBuilder::new();
+                    for v in values {
+                        match v {
+                            Value::Null => b.append_null(),
+                            Value::String(s) => b.append_value(s),
+                            // 嵌套的数组和对象保存为 json 字符串
+                            v => b.append_value(v.to_string()),
+                        }
+                    }
+                    Arc::new(b.finish())
+                }
+            };
+            Ok(array)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Array, Int64Array, StringArray};
+    use arrow_ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+    use std::io::Cursor;
+
+    fn write_all(format: OutputFormat) -> Result<Vec<u8>> {
+        let headers = vec!["Name".to_string(), "Kit".to_string(), "Score".to_string()];
+        let mut out = Vec::new();
+        let mut writer = Box::new(ArrowRowWriter::new(&mut out, format, &headers)?);
+        writer.write_row(&json!({"Name": "Perin", "Kit": 37, "Score": 1}))?;
+        writer.write_row(&json!({"Name": "Buffon", "Kit": null, "Score": 2.5}))?;
+        writer.finish()?;
+        Ok(out)
+    }
+
+    fn check(batch: &RecordBatch) {
+        let schema = batch.schema();
+        assert_eq!(schema.field(0).data_type(), &DataType::Utf8);
+        assert_eq!(schema.field(1).data_type(), &DataType::Int64);
+        assert_eq!(schema.field(2).data_type(), &DataType::Float64);
+        let names = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(names.value(1), "Buffon");
+        let kits = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(kits.value(0), 37);
+        assert!(kits.is_null(1));
+    }
+
+    #[test]
+    fn test_parquet_writer() -> Result<()> {
+        let mut file = tempfile::tempfile()?;
+        file.write_all(&write_all(OutputFormat::Parquet)?)?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        check(&batches[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_arrow_writer() -> Result<()> {
+        let content = write_all(OutputFormat::Arrow)?;
+        let reader = FileReader::try_new(Cursor::new(content), None)?;
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        check(&batches[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_types_widen_after_first_batch() -> Result<()> {
+        let headers = vec!["Kit".to_string(), "Score".to_string(), "Note".to_string()];
+        let mut out = Vec::new();
+        let mut writer = Box::new(ArrowRowWriter::new(
+            &mut out,
+            OutputFormat::Arrow,
+            &headers,
+        )?);
+        for i in 0..BATCH_ROWS {
+            writer.write_row(&json!({"Kit": i, "Score": i, "Note": null}))?;
+        }
+        writer.write_row(&json!({"Kit": "ten", "Score": 2.5, "Note": "injured"}))?;
+        writer.finish()?;
+
+        let reader = FileReader::try_new(Cursor::new(out), None)?;
+        let schema = reader.schema();
+        let types: Vec<_> = schema.fields().iter().map(|f| f.data_type()).collect();
+        assert_eq!(
+            types,
+            [&DataType::Utf8, &DataType::Float64, &DataType::Utf8]
+        );
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).sum::<usize>(),
+            BATCH_ROWS + 1
+        );
+        let kits = batches[1]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(kits.value(0), "ten");
+        Ok(())
+    }
+}
@@ -1,40 +1,262 @@
-use crate::cli::OutputFormat;
+use super::csv_filter::{check_filter_columns, select_columns, sort_rows};
+use super::csv_flatten::{flatten_rows, read_structured, unflatten_row};
+use super::csv_reader::open_csv;
+use super::csv_types::TypedRows;
+use super::csv_writer::{row_writer, write_csv_rows};
+use crate::cli::{
+    CsvFilterOpts, CsvFlattenOpts, CsvOutputOpts, CsvReaderOpts, CsvTypeOpts, InputFormat,
+    OutputFormat,
+};
+use crate::Progress;
 use anyhow::Result;
-use csv::Reader;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::fs;
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "PascalCase")]
-pub struct Player {
-    name: String,
-    position: String,
-    #[serde(rename = "DOB")]
-    dob: String,
-    nationality: String,
-    #[serde(rename = "Kit Number")]
-    kit: u8,
-}
+use serde_json::{Map, Value};
+
+/// Convert csv to `format` row by row, memory stays constant no matter how
+/// big the input is unless `--sort-by` is used. Column types are inferred
+/// from the first `--infer-rows` rows. Dotted and indexed headers become
+/// nested objects and arrays unless `--flat` is set.
+pub fn process_csv(
+    input: &str,
+    out: &CsvOutputOpts,
+    opts: &CsvReaderOpts,
+    type_opts: &CsvTypeOpts,
+    flatten: &CsvFlattenOpts,
+    filter: &CsvFilterOpts,
+    progress: bool,
+) -> Result<()> {
+    let (reader, headers) = open_csv(input, opts)?;
+    let mut rows = TypedRows::new(reader, headers, type_opts)?;
 
-pub fn process_csv(input: &str, output: String, format: OutputFormat) -> Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut ret = Vec::with_capacity(128);
-    let headers = reader.headers()?.clone();
-    for result in reader.records() {
-        let record = result?;
-        // headers.iter() 使用 headers 的迭代器
-        // record.iter() 使用 record 的迭代器
-        // zip() 将两个迭代器合并为一个元组的迭代器 [(header, record), ...]
-        // collect::<Value>() 将元组迭代器转换为 Json Value
-        // 因为 Json Value 也实现了迭代器 std::iter::Iterator trait
-        let json_value = headers.iter().zip(record.iter()).collect::<Value>();
-        ret.push(json_value);
-    }
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
+    let names: Vec<String> = rows.headers().iter().map(String::from).collect();
+    check_filter_columns(&names, filter)?;
+    let columns = if filter.select.is_empty() {
+        names
+    } else {
+        filter.select.clone()
+    };
+    let (file, output) = out.create()?;
+    let mut writer = row_writer(output, out.format(), &columns, opts)?;
+    // csv 和列式格式本身就是扁平的, 只有 json/yaml 这类格式才需要嵌套
+    let nest = !flatten.flat
+        && !matches!(
+            out.format(),
+            OutputFormat::Csv | OutputFormat::Parquet | OutputFormat::Arrow
+        );
+    let shape = |row: Map<String, Value>| {
+        let row = select_columns(row, &filter.select);
+        if nest {
+            unflatten_row(row)
+        } else {
+            Value::Object(row)
+        }
     };
-    fs::write(output, content)?;
+    let mut progress = Progress::new(progress);
+    let limit = filter.limit.unwrap_or(usize::MAX);
+    // 排序需要先拿到所有匹配的行, 不排序时边读边写
+    let mut sorted = Vec::new();
+    let mut written = 0;
+    loop {
+        if filter.sort_by.is_empty() && written >= limit {
+            break;
+        }
+        let Some(row) = rows.next() else {
+            break;
+        };
+        let row = row?;
+        progress.update(rows.rows(), rows.bytes());
+        if filter.filter.as_ref().is_some_and(|e| !e.eval(&row)) {
+            continue;
+        }
+        if filter.sort_by.is_empty() {
+            writer.write_row(&shape(row))?;
+            written += 1;
+        } else {
+            sorted.push(row);
+        }
+    }
+    if !filter.sort_by.is_empty() {
+        sort_rows(&mut sorted, &filter.sort_by);
+        for row in sorted.into_iter().take(limit) {
+            writer.write_row(&shape(row))?;
+        }
+    }
+    writer.finish()?;
+    file.commit()?;
+    progress.finish(rows.rows(), rows.bytes());
     Ok(())
 }
+
+/// Convert an array of objects from json/yaml/ndjson to csv, nested objects
+/// are flattened into dotted columns.
+pub fn process_to_csv(
+    input: &str,
+    out: &CsvOutputOpts,
+    from: InputFormat,
+    opts: &CsvReaderOpts,
+    flatten_opts: &CsvFlattenOpts,
+) -> Result<()> {
+    let rows = read_structured(input, from)?;
+    let (headers, rows) = flatten_rows(rows, flatten_opts);
+    let (file, output) = out.create()?;
+    write_csv_rows(output, &headers, &rows, opts, &flatten_opts.array_separator)?;
+    file.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ArrayMode, ColumnType};
+    use std::fs;
+    use std::path::Path;
+
+    fn to(output: &Path, format: OutputFormat) -> CsvOutputOpts {
+        CsvOutputOpts {
+            output: Some(output.display().to_string()),
+            format: Some(format),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_process_csv_streams_typed_rows() -> Result<()> {
+        let output = std::env::temp_dir().join("rcli_juventus.ndjson");
+        process_csv(
+            "assets/juventus.csv",
+            &to(&output, OutputFormat::Ndjson),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            false,
+        )?;
+        let content = fs::read_to_string(&output)?;
+        let rows: Vec<Value> = content
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(rows.len(), 27);
+        assert_eq!(rows[0]["Kit Number"], Value::from(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_infer_window() -> Result<()> {
+        let output = std::env::temp_dir().join("rcli_infer_window.json");
+        let type_opts = CsvTypeOpts {
+            infer_rows: 1,
+            ..Default::default()
+        };
+        process_csv(
+            "fixtures/infer_window.csv",
+            &to(&output, OutputFormat::Json),
+            &Default::default(),
+            &type_opts,
+            &Default::default(),
+            &Default::default(),
+            false,
+        )?;
+        let rows: Vec<Map<String, Value>> = serde_json::from_slice(&fs::read(&output)?)?;
+        assert_eq!(rows[0]["score"], 10);
+        assert_eq!(rows[1]["score"], "n/a");
+
+        // 用 --types 固定了类型的列仍然报错
+        let type_opts = CsvTypeOpts {
+            infer_rows: 1,
+            types: vec![("score".into(), ColumnType::Integer)],
+            ..Default::default()
+        };
+        let ret = process_csv(
+            "fixtures/infer_window.csv",
+            &to(&output, OutputFormat::Json),
+            &Default::default(),
+            &type_opts,
+            &Default::default(),
+            &Default::default(),
+            false,
+        );
+        assert!(ret.unwrap_err().to_string().contains("row 2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_filter_and_sort() -> Result<()> {
+        let output = std::env::temp_dir().join("rcli_juventus_filtered.json");
+        let filter = CsvFilterOpts {
+            select: vec!["Name".into(), "Kit Number".into()],
+            filter: Some("Nationality == 'Italy' && Kit Number > 10".parse()?),
+            sort_by: vec!["Kit Number:desc".parse()?],
+            limit: Some(2),
+        };
+        process_csv(
+            "assets/juventus.csv",
+            &to(&output, OutputFormat::Json),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            &filter,
+            false,
+        )?;
+        let rows: Vec<Map<String, Value>> = serde_json::from_slice(&fs::read(&output)?)?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].keys().collect::<Vec<_>>(), ["Name", "Kit Number"]);
+        assert!(rows[0]["Kit Number"].as_i64() > rows[1]["Kit Number"].as_i64());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ndjson_to_csv() -> Result<()> {
+        let output = std::env::temp_dir().join("rcli_players_from_ndjson.csv");
+        process_to_csv(
+            "fixtures/players.ndjson",
+            &to(&output, OutputFormat::Csv),
+            InputFormat::Ndjson,
+            &Default::default(),
+            &Default::default(),
+        )?;
+        let content = fs::read_to_string(&output)?;
+        assert_eq!(
+            content,
+            "name,position,kit,tags,address.city\n\
+             Perin,Goalkeeper,37,gk,\n\
+             Buffon,Goalkeeper,77,gk;captain,Turin\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let json = dir.path().join("nested.json");
+        process_csv(
+            "fixtures/nested.csv",
+            &to(&json, OutputFormat::Json),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            false,
+        )?;
+        let rows: Vec<Value> = serde_json::from_slice(&fs::read(&json)?)?;
+        assert_eq!(rows[0]["user"]["address"]["city"], "Turin");
+        assert_eq!(rows[0]["tags"], serde_json::json!(["gk"]));
+        assert_eq!(rows[1]["tags"][1], "captain");
+
+        let csv = dir.path().join("nested.csv");
+        let flatten = CsvFlattenOpts {
+            arrays: ArrayMode::Index,
+            ..Default::default()
+        };
+        process_to_csv(
+            &json.display().to_string(),
+            &to(&csv, OutputFormat::Csv),
+            InputFormat::Json,
+            &Default::default(),
+            &flatten,
+        )?;
+        assert_eq!(
+            fs::read_to_string(&csv)?,
+            fs::read_to_string("fixtures/nested.csv")?
+        );
+        Ok(())
+    }
+}
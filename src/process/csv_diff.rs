@@ -0,0 +1,385 @@
+use super::csv_reader::open_csv;
+use super::csv_sort::{key_indices, SortedRecords};
+use super::csv_writer::csv_writer;
+use crate::{CsvReaderOpts, DiffFormat};
+use anyhow::{anyhow, Result};
+use colored::{ColoredString, Colorize};
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::io::Write;
+
+/// One difference between the old and the new file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum RowChange {
+    Added {
+        key: Map<String, Value>,
+        row: Map<String, Value>,
+    },
+    Removed {
+        key: Map<String, Value>,
+        row: Map<String, Value>,
+    },
+    Changed {
+        key: Map<String, Value>,
+        fields: Vec<FieldChange>,
+        /// The new row, used by the patch output
+        #[serde(skip)]
+        row: Map<String, Value>,
+    },
+}
+
+/// A field whose value differs, `None` when the column only exists on one side
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub column: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DiffSummary {
+    pub added: u64,
+    pub removed: u64,
+    pub changed: u64,
+    pub unchanged: u64,
+}
+
+/// Rows of two csv files joined on the key columns, both sides are sorted
+/// externally so neither file has to fit in memory.
+pub struct CsvDiff {
+    /// Union of both headers, old columns first
+    pub headers: Vec<String>,
+    key: Vec<String>,
+    old: Side,
+    new: Side,
+    summary: DiffSummary,
+    /// The patch output is written in the dialect of the input
+    opts: CsvReaderOpts,
+}
+
+struct Side {
+    input: String,
+    headers: StringRecord,
+    rows: SortedRecords,
+    next: Option<(Vec<String>, Vec<String>)>,
+}
+
+pub fn diff_csv(
+    old: &str,
+    new: &str,
+    key: &[String],
+    opts: &CsvReaderOpts,
+    buffer_rows: usize,
+) -> Result<CsvDiff> {
+    if key.is_empty() {
+        return Err(anyhow!("At least one --key column is required"));
+    }
+    let old = Side::open(old, key, opts, buffer_rows)?;
+    let new = Side::open(new, key, opts, buffer_rows)?;
+    let mut headers: Vec<String> = old.headers.iter().map(String::from).collect();
+    for h in new.headers.iter() {
+        if !headers.iter().any(|x| x == h) {
+            headers.push(h.to_string());
+        }
+    }
+    Ok(CsvDiff {
+        headers,
+        key: key.to_vec(),
+        old,
+        new,
+        summary: DiffSummary::default(),
+        opts: opts.clone(),
+    })
+}
+
+/// Stream every change to `writer` and return the counts
+pub fn write_diff<W: Write>(
+    mut diff: CsvDiff,
+    writer: W,
+    format: DiffFormat,
+    color: bool,
+) -> Result<DiffSummary> {
+    match format {
+        DiffFormat::Text => {
+            let mut writer = writer;
+            while let Some(change) = diff.next_change()? {
+                write_text(&mut writer, &change, color)?;
+            }
+            let s = diff.summary;
+            writeln!(
+                writer,
+                "{} added, {} removed, {} changed, {} unchanged",
+                s.added, s.removed, s.changed, s.unchanged
+            )?;
+            writer.flush()?;
+        }
+        DiffFormat::Json => {
+            let mut writer = writer;
+            let mut first = true;
+            writer.write_all(b"[")?;
+            while let Some(change) = diff.next_change()? {
+                writer.write_all(if first { b"\n  " } else { b",\n  " })?;
+                serde_json::to_writer(&mut writer, &change)?;
+                first = false;
+            }
+            writer.write_all(if first { b"]\n" } else { b"\n]\n" })?;
+            writer.flush()?;
+        }
+        DiffFormat::Patch => {
+            let mut writer = csv_writer(writer, &diff.opts);
+            let mut header = vec!["_op".to_string()];
+            header.extend(diff.headers.iter().cloned());
+            writer.write_record(&header)?;
+            while let Some(change) = diff.next_change()? {
+                let (op, row) = match &change {
+                    RowChange::Added { row, .. } => ("add", row),
+                    RowChange::Removed { row, .. } => ("remove", row),
+                    RowChange::Changed { row, .. } => ("change", row),
+                };
+                let cells = diff
+                    .headers
+                    .iter()
+                    .map(|h| row.get(h).and_then(Value::as_str).unwrap_or_default());
+                writer.write_record(std::iter::once(op).chain(cells))?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(diff.summary)
+}
+
+impl CsvDiff {
+    /// The next added, removed or changed row in key order
+    pub fn next_change(&mut self) -> Result<Option<RowChange>> {
+        loop {
+            let ord = match (&self.old.next, &self.new.next) {
+                (None, None) => return Ok(None),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((a, _)), Some((b, _))) => a.cmp(b),
+            };
+            match ord {
+                Ordering::Less => {
+                    let (key, row) = self.old.advance()?;
+                    self.summary.removed += 1;
+                    return Ok(Some(RowChange::Removed {
+                        key: self.key_map(key),
+                        row: to_map(&self.old.headers, row),
+                    }));
+                }
+                Ordering::Greater => {
+                    let (key, row) = self.new.advance()?;
+                    self.summary.added += 1;
+                    return Ok(Some(RowChange::Added {
+                        key: self.key_map(key),
+                        row: to_map(&self.new.headers, row),
+                    }));
+                }
+                Ordering::Equal => {
+                    let (key, before) = self.old.advance()?;
+                    let (_, after) = self.new.advance()?;
+                    let fields = self.compare(&before, &after);
+                    if fields.is_empty() {
+                        self.summary.unchanged += 1;
+                        continue;
+                    }
+                    self.summary.changed += 1;
+                    return Ok(Some(RowChange::Changed {
+                        key: self.key_map(key),
+                        fields,
+                        row: to_map(&self.new.headers, after),
+                    }));
+                }
+            }
+        }
+    }
+
+    fn compare(&self, before: &[String], after: &[String]) -> Vec<FieldChange> {
+        let cell = |side: &Side, row: &[String], column: &str| {
+            let i = side.headers.iter().position(|h| h == column)?;
+            Some(row.get(i).cloned().unwrap_or_default())
+        };
+        self.headers
+            .iter()
+            .filter_map(|column| {
+                let before = cell(&self.old, before, column);
+                let after = cell(&self.new, after, column);
+                (before != after).then(|| FieldChange {
+                    column: column.clone(),
+                    before,
+                    after,
+                })
+            })
+            .collect()
+    }
+
+    fn key_map(&self, key: Vec<String>) -> Map<String, Value> {
+        self.key
+            .iter()
+            .cloned()
+            .zip(key.into_iter().map(Value::String))
+            .collect()
+    }
+}
+
+impl Side {
+    fn open(input: &str, key: &[String], opts: &CsvReaderOpts, buffer_rows: usize) -> Result<Self> {
+        let (reader, headers) = open_csv(input, opts)?;
+        let key = key_indices(&headers, key, input)?;
+        let mut rows = SortedRecords::new(reader, key, buffer_rows)?;
+        let next = rows.next().transpose()?;
+        Ok(Self {
+            input: input.to_string(),
+            headers,
+            rows,
+            next,
+        })
+    }
+
+    fn advance(&mut self) -> Result<(Vec<String>, Vec<String>)> {
+        let current = self.next.take().ok_or_else(|| anyhow!("no more rows"))?;
+        self.next = self.rows.next().transpose()?;
+        // 排序后重复的 key 一定相邻
+        if matches!(&self.next, Some((key, _)) if *key == current.0) {
+            return Err(anyhow!("Duplicate key {:?} in {}", current.0, self.input));
+        }
+        Ok(current)
+    }
+}
+
+fn to_map(headers: &StringRecord, row: Vec<String>) -> Map<String, Value> {
+    headers
+        .iter()
+        .map(String::from)
+        .zip(row.into_iter().map(Value::String))
+        .collect()
+}
+
+fn write_text(writer: &mut impl Write, change: &RowChange, color: bool) -> Result<()> {
+    let paint = |s: String, f: fn(&str) -> ColoredString| {
+        if color {
+            f(&s).to_string()
+        } else {
+            s
+        }
+    };
+    let key = |key: &Map<String, Value>| {
+        key.iter()
+            .map(|(k, v)| format!("{}={}", k, v.as_str().unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match change {
+        RowChange::Added { key: k, .. } => {
+            writeln!(writer, "{}", paint(format!("+ {}", key(k)), |s| s.green()))?
+        }
+        RowChange::Removed { key: k, .. } => {
+            writeln!(writer, "{}", paint(format!("- {}", key(k)), |s| s.red()))?
+        }
+        RowChange::Changed { key: k, fields, .. } => {
+            writeln!(writer, "{}", paint(format!("~ {}", key(k)), |s| s.yellow()))?;
+            for field in fields {
+                let show = |v: &Option<String>| match v {
+                    Some(v) => format!("{:?}", v),
+                    None => "(missing)".to_string(),
+                };
+                writeln!(
+                    writer,
+                    "    {}: {} -> {}",
+                    field.column,
+                    paint(show(&field.before), |s| s.red()),
+                    paint(show(&field.after), |s| s.green())
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::csv_sort::DEFAULT_SORT_BUFFER;
+
+    fn diff(format: DiffFormat, buffer_rows: usize) -> Result<(String, DiffSummary)> {
+        let diff = diff_csv(
+            "assets/juventus.csv",
+            "fixtures/juventus_new.csv",
+            &["Name".to_string()],
+            &Default::default(),
+            buffer_rows,
+        )?;
+        let mut out = Vec::new();
+        let summary = write_diff(diff, &mut out, format, false)?;
+        Ok((String::from_utf8(out)?, summary))
+    }
+
+    #[test]
+    fn test_diff_text() -> Result<()> {
+        let (text, summary) = diff(DiffFormat::Text, DEFAULT_SORT_BUFFER)?;
+        assert_eq!(
+            summary,
+            DiffSummary {
+                added: 1,
+                removed: 1,
+                changed: 2,
+                unchanged: 24,
+            }
+        );
+        assert!(text.contains("- Name=Gianluigi Buffon\n"));
+        assert!(text.contains("+ Name=Dejan Kulusevski\n"));
+        assert!(text.contains("~ Name=Mattia Perin\n    Kit Number: \"37\" -> \"1\"\n"));
+        // 用很小的排序缓冲区强制走外部排序, 结果应该一样
+        assert_eq!(diff(DiffFormat::Text, 3)?.0, text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_json_and_patch() -> Result<()> {
+        let (json, _) = diff(DiffFormat::Json, DEFAULT_SORT_BUFFER)?;
+        let changes: Vec<Value> = serde_json::from_str(&json)?;
+        assert_eq!(changes.len(), 4);
+        assert_eq!(changes[0]["op"], "added");
+
+        let (patch, _) = diff(DiffFormat::Patch, DEFAULT_SORT_BUFFER)?;
+        let mut lines = patch.lines();
+        assert_eq!(
+            lines.next(),
+            Some("_op,Name,Position,DOB,Nationality,Kit Number")
+        );
+        assert_eq!(lines.count(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_patch_keeps_dialect() -> Result<()> {
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            comment: Some(b'#'),
+            ..Default::default()
+        };
+        let input = "fixtures/players_semicolon.csv";
+        let diff = diff_csv(input, input, &["Name".to_string()], &opts, 10)?;
+        let mut out = Vec::new();
+        write_diff(diff, &mut out, DiffFormat::Patch, false)?;
+        assert_eq!(String::from_utf8(out)?, "_op;Name;Position;Kit Number\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_duplicate_key() -> Result<()> {
+        let diff = diff_csv(
+            "fixtures/juventus_invalid.csv",
+            "assets/juventus.csv",
+            &["Name".to_string()],
+            &Default::default(),
+            DEFAULT_SORT_BUFFER,
+        )?;
+        let err = write_diff(diff, Vec::new(), DiffFormat::Json, false).unwrap_err();
+        assert!(err.to_string().contains("Duplicate key"));
+        Ok(())
+    }
+}
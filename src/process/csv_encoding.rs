@@ -0,0 +1,191 @@
+use crate::CsvEncoding;
+use anyhow::Result;
+use chardetng::EncodingDetector;
+use encoding_rs::{Decoder, DecoderResult, Encoding, UTF_8};
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read};
+
+/// Bytes looked at to guess the encoding with `--encoding auto`
+const SNIFF_BYTES: usize = 64 * 1024;
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Transcode `reader` to utf-8 so the csv parser can read it.
+///
+/// A BOM is stripped and always wins over `encoding`. With `auto` the
+/// encoding is guessed from the first 64 KiB. Invalid byte sequences are
+/// reported with their line number instead of being replaced.
+pub fn decode_reader(mut reader: Box<dyn Read>, encoding: CsvEncoding) -> Result<Box<dyn Read>> {
+    let sniff = match encoding {
+        CsvEncoding::Auto => SNIFF_BYTES,
+        // 只需要看有没有 BOM
+        CsvEncoding::Fixed(_) => 3,
+    };
+    let mut pending = Vec::with_capacity(sniff);
+    let eof = fill(&mut reader, &mut pending, sniff)?;
+    let encoding = match (Encoding::for_bom(&pending), encoding) {
+        (Some((encoding, bom)), _) => {
+            pending.drain(..bom);
+            encoding
+        }
+        (None, CsvEncoding::Fixed(encoding)) => encoding,
+        (None, CsvEncoding::Auto) => detect(&pending, eof),
+    };
+    Ok(Box::new(DecodeReader {
+        inner: reader,
+        decoder: encoding.new_decoder_without_bom_handling(),
+        encoding,
+        pending,
+        eof,
+        out: Vec::new(),
+        pos: 0,
+        line: 1,
+        error: None,
+    }))
+}
+
+fn detect(prefix: &[u8], eof: bool) -> &'static Encoding {
+    // 前缀是合法的 utf-8 时直接用 utf-8, 末尾可能截断了一个多字节字符;
+    // 否则纯 ascii 的前缀会被猜成 windows-1252, 后面的中文就乱码了
+    let valid = Encoding::utf8_valid_up_to(prefix);
+    if valid == prefix.len() || (!eof && prefix.len() - valid < 4) {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(prefix, eof);
+    detector.guess(None, true)
+}
+
+// 读满 len 个字节或者读到结尾, 返回是否已经读到结尾
+fn fill(reader: &mut dyn Read, buf: &mut Vec<u8>, len: usize) -> io::Result<bool> {
+    let n = reader.take(len as u64).read_to_end(buf)?;
+    Ok(n < len)
+}
+
+/// An invalid byte sequence, carried inside the `io::Error` the reader returns
+#[derive(Debug, Clone)]
+pub struct DecodeError {
+    pub line: u64,
+    pub encoding: &'static Encoding,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}: invalid {} byte sequence, set --encoding (or --encoding auto)",
+            self.line,
+            self.encoding.name()
+        )
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+struct DecodeReader {
+    inner: Box<dyn Read>,
+    decoder: Decoder,
+    encoding: &'static Encoding,
+    /// Input bytes not decoded yet
+    pending: Vec<u8>,
+    eof: bool,
+    /// Decoded utf-8 not handed out yet, starting at `pos`
+    out: Vec<u8>,
+    pos: usize,
+    line: u64,
+    /// Reported once the output decoded before the bad bytes is consumed
+    error: Option<DecodeError>,
+}
+
+impl Read for DecodeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pos < self.out.len() {
+                let n = buf.len().min(self.out.len() - self.pos);
+                buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            if let Some(e) = self.error.take() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+            if self.eof && self.pending.is_empty() {
+                return Ok(0);
+            }
+            if !self.eof && self.pending.len() < CHUNK_BYTES {
+                self.eof = fill(&mut self.inner, &mut self.pending, CHUNK_BYTES)?;
+            }
+            self.decode();
+        }
+    }
+}
+
+impl DecodeReader {
+    fn decode(&mut self) {
+        let len = self
+            .decoder
+            .max_utf8_buffer_length_without_replacement(self.pending.len())
+            .unwrap_or(CHUNK_BYTES * 3);
+        self.out.resize(len, 0);
+        self.pos = 0;
+        let (result, read, written) =
+            self.decoder
+                .decode_to_utf8_without_replacement(&self.pending, &mut self.out, self.eof);
+        self.out.truncate(written);
+        self.pending.drain(..read);
+        self.line += self.out.iter().filter(|&&b| b == b'\n').count() as u64;
+        if let DecoderResult::Malformed(..) = result {
+            self.error = Some(DecodeError {
+                line: self.line,
+                encoding: self.encoding,
+            });
+            // 出错之后不再继续解码
+            self.pending.clear();
+            self.eof = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn decode(bytes: &[u8], encoding: &str) -> io::Result<String> {
+        let reader = Box::new(Cursor::new(bytes.to_vec()));
+        let mut reader = decode_reader(reader, encoding.parse().unwrap()).unwrap();
+        let mut s = String::new();
+        reader.read_to_string(&mut s)?;
+        Ok(s)
+    }
+
+    #[test]
+    fn test_strip_bom() -> io::Result<()> {
+        assert_eq!(decode(b"\xef\xbb\xbfName\n", "utf-8")?, "Name\n");
+        // utf-16 的 BOM 优先于 --encoding
+        let utf16: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("Name,Higuaín\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(decode(&utf16, "utf-8")?, "Name,Higuaín\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_and_detected_encoding() -> io::Result<()> {
+        let latin1 = b"Name,Nationality\nGonzalo Higua\xedn,Argentina\n";
+        assert!(decode(latin1, "windows-1252")?.contains("Higuaín"));
+        assert!(decode(latin1, "auto")?.contains("Higuaín"));
+        assert_eq!(decode("Dybala\n".as_bytes(), "auto")?, "Dybala\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_bytes_report_line() {
+        let err = decode(b"Name\nBuffon\nHigua\xedn\n", "utf-8").unwrap_err();
+        assert!(
+            err.to_string().starts_with("line 3: invalid UTF-8"),
+            "{}",
+            err
+        );
+    }
+}
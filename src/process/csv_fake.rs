@@ -0,0 +1,337 @@
+use super::csv_validate::{ColumnSchema, CsvSchema};
+use super::csv_writer::row_writer;
+use crate::{ColumnType, CsvOutputOpts, CsvReaderOpts};
+use anyhow::{anyhow, Result};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use time::{Date, Month};
+
+/// Attempts at a fresh value for a `unique` column before giving up
+const UNIQUE_RETRIES: usize = 1000;
+
+const FIRST_NAMES: &[&str] = &[
+    "Alessandro",
+    "Andrea",
+    "Anna",
+    "Chiara",
+    "Daniel",
+    "David",
+    "Elena",
+    "Emma",
+    "Federico",
+    "Francesca",
+    "Gianluca",
+    "Giorgio",
+    "Giulia",
+    "Hannah",
+    "Ivan",
+    "James",
+    "Julia",
+    "Laura",
+    "Leonardo",
+    "Lucas",
+    "Luca",
+    "Marco",
+    "Maria",
+    "Mateo",
+    "Matteo",
+    "Noah",
+    "Olivia",
+    "Paolo",
+    "Pedro",
+    "Sara",
+    "Sofia",
+    "Thomas",
+    "Valentina",
+    "Wojciech",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Bernardi",
+    "Bianchi",
+    "Colombo",
+    "Costa",
+    "Esposito",
+    "Ferrari",
+    "Fernandez",
+    "Garcia",
+    "Gomez",
+    "Greco",
+    "Hoffmann",
+    "Kovac",
+    "Lopez",
+    "Marino",
+    "Martin",
+    "Moretti",
+    "Muller",
+    "Novak",
+    "Petrovic",
+    "Ricci",
+    "Romano",
+    "Rossi",
+    "Russo",
+    "Santos",
+    "Silva",
+    "Smith",
+    "Szczesny",
+    "Weber",
+    "Williams",
+];
+
+const EMAIL_DOMAINS: &[&str] = &["example.com", "example.org", "example.net"];
+
+/// Kind of realistic value a column holds, see `fake` in the schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FakeKind {
+    /// "First Last"
+    Name,
+    FirstName,
+    LastName,
+    Email,
+    /// A random (v4) uuid
+    Uuid,
+    /// An ISO 8601 date, `min`/`max` are the years
+    Date,
+}
+
+enum Generator {
+    Choice(Vec<String>),
+    Regex(Box<rand_regex::Regex>),
+    Fake(FakeKind),
+    Date(i32, i32),
+    Int(i64, i64),
+    Float(f64, f64),
+    Bool,
+}
+
+/// Generate `rows` rows that satisfy `schema`, the same seed always gives
+/// the same rows.
+pub fn process_csv_fake(
+    schema: &CsvSchema,
+    rows: usize,
+    seed: Option<u64>,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let generators = schema
+        .columns
+        .iter()
+        .map(generator)
+        .collect::<Result<Vec<_>>>()?;
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let headers: Vec<String> = schema.columns.iter().map(|c| c.name.clone()).collect();
+    let (file, output) = out.create()?;
+    let mut writer = row_writer(output, out.format(), &headers, &CsvReaderOpts::default())?;
+    let mut seen: Vec<HashSet<String>> = vec![HashSet::new(); headers.len()];
+    for _ in 0..rows {
+        let mut row = Map::new();
+        for ((column, gen), seen) in schema.columns.iter().zip(&generators).zip(&mut seen) {
+            let mut value = gen.generate(&mut rng);
+            if column.unique {
+                let mut retries = 0;
+                while !seen.insert(key(&value)) {
+                    retries += 1;
+                    if retries > UNIQUE_RETRIES {
+                        return Err(anyhow!(
+                            "{}: ran out of unique values after {} rows",
+                            column.name,
+                            seen.len()
+                        ));
+                    }
+                    value = gen.generate(&mut rng);
+                }
+            }
+            row.insert(column.name.clone(), value);
+        }
+        writer.write_row(&Value::Object(row))?;
+    }
+    writer.finish()?;
+    file.commit()
+}
+
+fn key(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+// 依次看 enum, fake, pattern, 都没有时按类型(和列名)生成
+fn generator(column: &ColumnSchema) -> Result<Generator> {
+    if let Some(allowed) = &column.allowed {
+        if allowed.is_empty() {
+            return Err(anyhow!("{}: enum is empty", column.name));
+        }
+        return Ok(Generator::Choice(allowed.clone()));
+    }
+    if let (None, Some(pattern)) = (column.fake, &column.pattern) {
+        let regex = compile_pattern(pattern)
+            .map_err(|e| anyhow!("{}: cannot generate from {:?}: {}", column.name, pattern, e))?;
+        return Ok(Generator::Regex(Box::new(regex)));
+    }
+    let lower = column.name.to_lowercase();
+    let fake = column.fake.or(match column.ty {
+        ColumnType::Date => Some(FakeKind::Date),
+        ColumnType::String if lower.contains("email") => Some(FakeKind::Email),
+        ColumnType::String if lower.contains("name") => Some(FakeKind::Name),
+        _ => None,
+    });
+    let generator = match (fake, column.ty) {
+        (Some(FakeKind::Date), _) => Generator::Date(
+            column.min.map(|y| y as i32).unwrap_or(1970),
+            column.max.map(|y| y as i32).unwrap_or(2005),
+        ),
+        (Some(kind), _) => Generator::Fake(kind),
+        (None, ColumnType::Integer) => Generator::Int(
+            column.min.map(|v| v.ceil() as i64).unwrap_or(0),
+            column.max.map(|v| v.floor() as i64).unwrap_or(1000),
+        ),
+        (None, ColumnType::Float) => {
+            Generator::Float(column.min.unwrap_or(0.0), column.max.unwrap_or(1000.0))
+        }
+        (None, ColumnType::Boolean) => Generator::Bool,
+        (None, _) => Generator::Regex(Box::new(compile_pattern("[a-z]{4,10}")?)),
+    };
+    match generator {
+        Generator::Int(min, max) if min > max => Err(anyhow!("{}: min > max", column.name)),
+        Generator::Float(min, max) if min > max => Err(anyhow!("{}: min > max", column.name)),
+        Generator::Date(min, max) if min > max => Err(anyhow!("{}: min > max", column.name)),
+        generator => Ok(generator),
+    }
+}
+
+// rand_regex 不支持锚点, 校验用的 ^...$ 去掉即可; 关掉 unicode 让 \d 只生成 ascii 数字
+fn compile_pattern(pattern: &str) -> Result<rand_regex::Regex> {
+    let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+    let pattern = match pattern.strip_suffix('$') {
+        Some(p) if !p.ends_with('\\') => p,
+        _ => pattern,
+    };
+    let hir = regex_syntax::ParserBuilder::new()
+        .unicode(false)
+        .build()
+        .parse(pattern)?;
+    Ok(rand_regex::Regex::with_hir(hir, 10)?)
+}
+
+impl Generator {
+    fn generate(&self, rng: &mut StdRng) -> Value {
+        match self {
+            Generator::Choice(values) => values.choose(rng).cloned().into(),
+            Generator::Regex(regex) => Value::String(rng.sample::<String, _>(regex.as_ref())),
+            Generator::Fake(kind) => Value::String(fake(*kind, rng)),
+            Generator::Date(from, to) => Value::String(random_date(rng, *from, *to)),
+            Generator::Int(min, max) => Value::from(rng.gen_range(*min..=*max)),
+            Generator::Float(min, max) => {
+                let v: f64 = rng.gen_range(*min..=*max);
+                Value::from((v * 100.0).round() / 100.0)
+            }
+            Generator::Bool => Value::Bool(rng.gen()),
+        }
+    }
+}
+
+fn random_date(rng: &mut StdRng, from: i32, to: i32) -> String {
+    let year = rng.gen_range(from..=to);
+    let start = Date::from_calendar_date(year, Month::January, 1)
+        .map(|d| d.to_julian_day())
+        .unwrap_or_default();
+    Date::from_julian_day(start + rng.gen_range(0..365))
+        .unwrap_or(Date::MIN)
+        .to_string()
+}
+
+fn pick<'a>(values: &[&'a str], rng: &mut StdRng) -> &'a str {
+    values.choose(rng).copied().unwrap_or_default()
+}
+
+fn fake(kind: FakeKind, rng: &mut StdRng) -> String {
+    match kind {
+        FakeKind::Name => format!("{} {}", pick(FIRST_NAMES, rng), pick(LAST_NAMES, rng)),
+        FakeKind::FirstName => pick(FIRST_NAMES, rng).to_string(),
+        FakeKind::LastName => pick(LAST_NAMES, rng).to_string(),
+        FakeKind::Email => format!(
+            "{}.{}{}@{}",
+            pick(FIRST_NAMES, rng).to_lowercase(),
+            pick(LAST_NAMES, rng).to_lowercase(),
+            rng.gen_range(1..100),
+            pick(EMAIL_DOMAINS, rng)
+        ),
+        FakeKind::Uuid => {
+            let mut b: [u8; 16] = rng.gen();
+            // version 4, variant RFC 4122
+            b[6] = (b[6] & 0x0f) | 0x40;
+            b[8] = (b[8] & 0x3f) | 0x80;
+            let hex: String = b.iter().map(|x| format!("{:02x}", x)).collect();
+            format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            )
+        }
+        FakeKind::Date => random_date(rng, 1970, 2005),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_csv_validate;
+    use std::fs;
+
+    fn fake_to(schema: &str, rows: usize, seed: u64, dir: &tempfile::TempDir) -> Result<String> {
+        let path = dir.path().join(format!("fake_{}.csv", seed));
+        let out = CsvOutputOpts {
+            output: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        process_csv_fake(&CsvSchema::load(schema)?, rows, Some(seed), &out)?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn test_fake_rows_pass_validation() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for schema in [
+            "fixtures/juventus.schema.yaml",
+            "fixtures/people.schema.yaml",
+        ] {
+            let path = fake_to(schema, 50, 7, &dir)?;
+            let errors =
+                process_csv_validate(&path, &CsvSchema::load(schema)?, &Default::default())?;
+            assert!(errors.is_empty(), "{}: {:?}", schema, errors);
+            assert_eq!(fs::read_to_string(&path)?.lines().count(), 51);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fake_is_reproducible() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let a = fs::read_to_string(fake_to("fixtures/people.schema.yaml", 20, 42, &dir)?)?;
+        let b = fs::read_to_string(fake_to("fixtures/people.schema.yaml", 20, 42, &dir)?)?;
+        assert_eq!(a, b);
+        let c = fs::read_to_string(fake_to("fixtures/people.schema.yaml", 20, 43, &dir)?)?;
+        assert_ne!(a, c);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fake_unique_exhausted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        // Kit Number 是 1..=99 且唯一, 100 行一定不够
+        let err = fake_to("fixtures/juventus.schema.yaml", 100, 1, &dir).unwrap_err();
+        assert!(err.to_string().contains("Kit Number"));
+        Ok(())
+    }
+}
@@ -0,0 +1,551 @@
+use crate::CsvFilterOpts;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A boolean expression over the columns of a row, e.g.
+/// `Nationality == 'Italy' && Kit Number > 10`.
+///
+/// Column names are bare words (spaces allowed) or `"double quoted"`, string
+/// literals are `'single quoted'`. Supported operators are `== != < <= > >=`,
+/// `=~` for regex match, `&& || !` and parentheses.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CmpOp, Operand),
+    Match(Operand, Regex),
+    /// A lone operand is true when it is not null, false, 0 or empty
+    Truthy(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Column(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// `--sort-by Name` or `--sort-by DOB:desc`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl Expr {
+    pub fn eval(&self, row: &Map<String, Value>) -> bool {
+        match self {
+            Expr::Or(a, b) => a.eval(row) || b.eval(row),
+            Expr::And(a, b) => a.eval(row) && b.eval(row),
+            Expr::Not(e) => !e.eval(row),
+            Expr::Compare(a, op, b) => {
+                let ord = compare_values(&a.eval(row), &b.eval(row));
+                match op {
+                    CmpOp::Eq => ord == Some(Ordering::Equal),
+                    CmpOp::Ne => ord != Some(Ordering::Equal),
+                    CmpOp::Lt => ord == Some(Ordering::Less),
+                    CmpOp::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+                    CmpOp::Gt => ord == Some(Ordering::Greater),
+                    CmpOp::Ge => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+                }
+            }
+            Expr::Match(a, re) => match a.eval(row) {
+                Value::Null => false,
+                Value::String(s) => re.is_match(&s),
+                v => re.is_match(&v.to_string()),
+            },
+            Expr::Truthy(a) => match a.eval(row) {
+                Value::Null => false,
+                Value::Bool(b) => b,
+                Value::Number(n) => n.as_f64() != Some(0.0),
+                Value::String(s) => !s.is_empty(),
+                Value::Array(a) => !a.is_empty(),
+                Value::Object(o) => !o.is_empty(),
+            },
+        }
+    }
+
+    /// All column names referenced by the expression
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns
+    }
+
+    fn collect_columns<'a>(&'a self, out: &mut Vec<&'a str>) {
+        let mut push = |o: &'a Operand| {
+            if let Operand::Column(name) = o {
+                out.push(name.as_str());
+            }
+        };
+        match self {
+            Expr::Or(a, b) | Expr::And(a, b) => {
+                a.collect_columns(out);
+                b.collect_columns(out);
+            }
+            Expr::Not(e) => e.collect_columns(out),
+            Expr::Compare(a, _, b) => {
+                push(a);
+                push(b);
+            }
+            Expr::Match(a, _) | Expr::Truthy(a) => push(a),
+        }
+    }
+}
+
+impl Operand {
+    fn eval(&self, row: &Map<String, Value>) -> Value {
+        match self {
+            Operand::Column(name) => row.get(name).cloned().unwrap_or(Value::Null),
+            Operand::Literal(v) => v.clone(),
+        }
+    }
+}
+
+/// Order two cell values, numbers compare numerically (numeric strings
+/// included), null only equals null.
+pub fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => match (as_number(a), as_number(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => Some(to_text(a).cmp(&to_text(b))),
+        },
+    }
+}
+
+fn as_number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn to_text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// Apply `--select` to a row, keeping the order of the select list
+pub fn select_columns(row: Map<String, Value>, columns: &[String]) -> Map<String, Value> {
+    if columns.is_empty() {
+        return row;
+    }
+    let mut row = row;
+    columns
+        .iter()
+        .map(|c| (c.clone(), row.remove(c).unwrap_or(Value::Null)))
+        .collect()
+}
+
+/// Sort rows by the keys in order, nulls always go last
+pub fn sort_rows(rows: &mut [Map<String, Value>], keys: &[SortKey]) {
+    rows.sort_by(|a, b| {
+        for key in keys {
+            let (x, y) = (
+                a.get(&key.column).unwrap_or(&Value::Null),
+                b.get(&key.column).unwrap_or(&Value::Null),
+            );
+            let ord = match (x.is_null(), y.is_null()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                _ => {
+                    let ord = compare_values(x, y).unwrap_or(Ordering::Equal);
+                    if key.descending {
+                        ord.reverse()
+                    } else {
+                        ord
+                    }
+                }
+            };
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        Ordering::Equal
+    });
+}
+
+/// Make sure every column used by `--select`, `--where` and `--sort-by` exists
+pub fn check_filter_columns(headers: &[String], opts: &CsvFilterOpts) -> Result<()> {
+    let used = opts
+        .select
+        .iter()
+        .map(String::as_str)
+        .chain(opts.filter.iter().flat_map(|e| e.columns()))
+        .chain(opts.sort_by.iter().map(|k| k.column.as_str()));
+    for column in used {
+        if !headers.iter().any(|h| h == column) {
+            return Err(anyhow!("Unknown column {:?}", column));
+        }
+    }
+    Ok(())
+}
+
+impl Display for CmpOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, descending) = match s.rsplit_once(':') {
+            Some((column, "desc")) => (column, true),
+            Some((column, "asc")) => (column, false),
+            _ => (s, false),
+        };
+        if column.is_empty() {
+            return Err(anyhow!("Empty sort column"));
+        }
+        Ok(SortKey {
+            column: column.to_string(),
+            descending,
+        })
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(anyhow!("Unexpected {} in expression", token));
+        }
+        Ok(expr)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Column(String),
+    Str(String),
+    Cmp(CmpOp),
+    Match,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "{:?}", w),
+            Token::Column(c) => write!(f, "column {:?}", c),
+            Token::Str(s) => write!(f, "'{}'", s),
+            Token::Cmp(op) => write!(f, "{}", op),
+            Token::Match => write!(f, "=~"),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let two = |chars: &mut std::iter::Peekable<std::str::Chars>, next: char| {
+            chars.next();
+            if chars.peek() == Some(&next) {
+                chars.next();
+                true
+            } else {
+                false
+            }
+        };
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            '&' if two(&mut chars, '&') => tokens.push(Token::And),
+            '|' if two(&mut chars, '|') => tokens.push(Token::Or),
+            '=' => {
+                chars.next();
+                match chars.next() {
+                    Some('=') => tokens.push(Token::Cmp(CmpOp::Eq)),
+                    Some('~') => tokens.push(Token::Match),
+                    _ => return Err(anyhow!("Expect == or =~")),
+                }
+            }
+            '!' if two(&mut chars, '=') => tokens.push(Token::Cmp(CmpOp::Ne)),
+            '!' => tokens.push(Token::Not),
+            '<' if two(&mut chars, '=') => tokens.push(Token::Cmp(CmpOp::Le)),
+            '<' => tokens.push(Token::Cmp(CmpOp::Lt)),
+            '>' if two(&mut chars, '=') => tokens.push(Token::Cmp(CmpOp::Ge)),
+            '>' => tokens.push(Token::Cmp(CmpOp::Gt)),
+            '\'' | '"' | '`' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        // 引号内用两个引号表示一个引号, 如 'O''Neil'
+                        Some(q) if q == c && chars.peek() == Some(&c) => {
+                            chars.next();
+                            text.push(c);
+                        }
+                        Some(q) if q == c => break,
+                        Some(ch) => text.push(ch),
+                        None => return Err(anyhow!("Unterminated quote in expression")),
+                    }
+                }
+                tokens.push(if c == '\'' {
+                    Token::Str(text)
+                } else {
+                    Token::Column(text)
+                });
+            }
+            '&' | '|' => return Err(anyhow!("Expect && or ||")),
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || "()&|=!<>'\"`".contains(ch) {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.next();
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.next();
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(anyhow!("Missing ) in expression")),
+                }
+            }
+            _ => self.parse_comparison(),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let left = self.parse_operand()?;
+        match self.peek() {
+            Some(Token::Cmp(op)) => {
+                let op = *op;
+                self.next();
+                Ok(Expr::Compare(left, op, self.parse_operand()?))
+            }
+            Some(Token::Match) => {
+                self.next();
+                match self.next() {
+                    Some(Token::Str(pattern)) => Ok(Expr::Match(left, Regex::new(&pattern)?)),
+                    _ => Err(anyhow!("=~ expects a 'quoted' regex")),
+                }
+            }
+            _ => Ok(Expr::Truthy(left)),
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Operand::Literal(Value::String(s))),
+            Some(Token::Column(c)) => Ok(Operand::Column(c)),
+            Some(Token::Word(w)) => {
+                if let Some(literal) = parse_literal(&w) {
+                    return Ok(Operand::Literal(literal));
+                }
+                // 连续的单词组成一个列名, 如 Kit Number
+                let mut words = vec![w];
+                while let Some(Token::Word(w)) = self.peek() {
+                    words.push(w.clone());
+                    self.next();
+                }
+                Ok(Operand::Column(words.join(" ")))
+            }
+            Some(token) => Err(anyhow!("Unexpected {} in expression", token)),
+            None => Err(anyhow!("Unexpected end of expression")),
+        }
+    }
+}
+
+fn parse_literal(word: &str) -> Option<Value> {
+    match word {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        "null" => Some(Value::Null),
+        _ => {
+            if let Ok(i) = word.parse::<i64>() {
+                Some(Value::from(i))
+            } else {
+                word.parse::<f64>()
+                    .ok()
+                    .filter(|f| f.is_finite())
+                    .map(Value::from)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row(v: Value) -> Map<String, Value> {
+        v.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_parse_and_eval() -> Result<()> {
+        let expr: Expr = "Nationality == 'Italy' && Kit Number > 10".parse()?;
+        assert_eq!(expr.columns(), ["Nationality", "Kit Number"]);
+        assert!(expr.eval(&row(json!({"Nationality": "Italy", "Kit Number": 37}))));
+        assert!(!expr.eval(&row(json!({"Nationality": "Italy", "Kit Number": 1}))));
+        assert!(!expr.eval(&row(json!({"Nationality": "Poland", "Kit Number": 37}))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_precedence_and_not() -> Result<()> {
+        let expr: Expr = "a == 1 || a == 2 && !(b)".parse()?;
+        assert!(expr.eval(&row(json!({"a": 1, "b": true}))));
+        assert!(expr.eval(&row(json!({"a": 2, "b": false}))));
+        assert!(!expr.eval(&row(json!({"a": 2, "b": true}))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_regex_and_quotes() -> Result<()> {
+        let expr: Expr = r#""DOB" =~ '19(8|9)\d' && Name != 'O''Neil'"#.parse()?;
+        assert!(expr.eval(&row(json!({"DOB": "Apr 18, 1990 (29)", "Name": "Perin"}))));
+        assert!(!expr.eval(&row(json!({"DOB": "Jan 28, 1978 (41)", "Name": "Buffon"}))));
+        assert!(!expr.eval(&row(json!({"DOB": "Apr 18, 1990 (29)", "Name": "O'Neil"}))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("a ==".parse::<Expr>().is_err());
+        assert!("(a == 1".parse::<Expr>().is_err());
+        assert!("a = 1".parse::<Expr>().is_err());
+        assert!("a == 'x".parse::<Expr>().is_err());
+        assert!("a == 1 )".parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn test_null_comparisons() -> Result<()> {
+        let expr: Expr = "Kit > 10".parse()?;
+        assert!(!expr.eval(&row(json!({"Kit": null}))));
+        let expr: Expr = "Kit == null".parse()?;
+        assert!(expr.eval(&row(json!({"Kit": null}))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_rows() -> Result<()> {
+        let mut rows = vec![
+            row(json!({"n": "b", "k": 2})),
+            row(json!({"n": "a", "k": null})),
+            row(json!({"n": "c", "k": 10})),
+            row(json!({"n": "d", "k": 2})),
+        ];
+        sort_rows(&mut rows, &["k:desc".parse()?, "n:desc".parse()?]);
+        let names: Vec<_> = rows.iter().map(|r| r["n"].as_str().unwrap()).collect();
+        assert_eq!(names, ["c", "d", "b", "a"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_columns() {
+        let selected = select_columns(
+            row(json!({"a": 1, "b": 2, "c": 3})),
+            &["c".to_string(), "a".to_string()],
+        );
+        assert_eq!(selected.keys().collect::<Vec<_>>(), ["c", "a"]);
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{get_reader, ArrayMode, CsvFlattenOpts, InputFormat};
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader};
+
+/// Read an array of objects from a json/yaml document or an ndjson stream
+pub fn read_structured(input: &str, format: InputFormat) -> Result<Vec<Value>> {
+    let reader = get_reader(input)?;
+    let value: Value = match format {
+        InputFormat::Json => serde_json::from_reader(reader)?,
+        InputFormat::Yaml => serde_yaml::from_reader(reader)?,
+        InputFormat::Ndjson => {
+            let mut rows = Vec::new();
+            for (i, line) in BufReader::new(reader).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let row =
+                    serde_json::from_str(&line).map_err(|e| anyhow!("line {}: {}", i + 1, e))?;
+                rows.push(row);
+            }
+            Value::Array(rows)
+        }
+        InputFormat::Csv => return Err(anyhow!("csv is not a structured format")),
+    };
+    let rows = match value {
+        Value::Array(rows) => rows,
+        // 单个对象当作只有一行
+        Value::Object(_) => vec![value],
+        _ => return Err(anyhow!("Expect an array of objects")),
+    };
+    if let Some(i) = rows.iter().position(|row| !row.is_object()) {
+        return Err(anyhow!("Row {} is not an object", i + 1));
+    }
+    Ok(rows)
+}
+
+/// Flatten nested rows into flat ones keyed by dotted paths (`address.city`),
+/// arrays become indexed keys (`tags[0]`) with `--arrays index`.
+///
+/// Returns the header, the union of all keys in first-seen order, together
+/// with the flat rows.
+pub fn flatten_rows(
+    rows: Vec<Value>,
+    opts: &CsvFlattenOpts,
+) -> (Vec<String>, Vec<Map<String, Value>>) {
+    let rows: Vec<Value> = match opts.arrays {
+        ArrayMode::Join | ArrayMode::Index => rows,
+        ArrayMode::Explode => rows.into_iter().flat_map(explode).collect(),
+    };
+    let indexed = opts.arrays == ArrayMode::Index;
+    // preserve_order 开启后 Map 会保持插入顺序, 用它来收集表头
+    let mut headers = Map::new();
+    let flat_rows: Vec<_> = rows
+        .into_iter()
+        .map(|row| {
+            let mut flat = Map::new();
+            flatten_value("", row, indexed, &mut flat);
+            for key in flat.keys() {
+                if !headers.contains_key(key) {
+                    headers.insert(key.clone(), Value::Null);
+                }
+            }
+            flat
+        })
+        .collect();
+    (headers.into_iter().map(|(k, _)| k).collect(), flat_rows)
+}
+
+/// Flatten one row, nested objects become dotted keys
+pub fn flatten_row(row: Value) -> Map<String, Value> {
+    let mut flat = Map::new();
+    flatten_value("", row, false, &mut flat);
+    flat
+}
+
+fn flatten_value(prefix: &str, value: Value, indexed: bool, out: &mut Map<String, Value>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, v) in map {
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_value(&key, v, indexed, out);
+            }
+        }
+        // 空数组不产生列, 反向转换时缺少的下标也不会生成元素
+        Value::Array(items) if indexed => {
+            for (i, v) in items.into_iter().enumerate() {
+                flatten_value(&format!("{}[{}]", prefix, i), v, indexed, out);
+            }
+        }
+        Value::Object(_) => {
+            out.insert(prefix.to_string(), Value::Null);
+        }
+        // 数组(join 模式)保持原样, 由写 csv 时拼接成一个单元格
+        v => {
+            out.insert(prefix.to_string(), v);
+        }
+    }
+}
+
+/// Build nested objects and arrays from dotted and indexed keys
+/// (`user.address.city`, `tags[0]`), the reverse of `--arrays index`.
+///
+/// Keys that are not a valid path, that clash with another key (`user.name`
+/// next to `user`, in any order), or whose index is not below the number of
+/// columns are kept as they are. Trailing empty elements are dropped from
+/// arrays since they come from rows with fewer elements.
+pub fn unflatten_row(row: Map<String, Value>) -> Value {
+    let max_index = row.len();
+    let keys: HashSet<String> = row.keys().cloned().collect();
+    let mut nested = Value::Object(Map::new());
+    let mut literal = Vec::new();
+    for (key, value) in row {
+        let path = parse_path(&key).filter(|path| {
+            // 一个很大的下标会让数组分配大量内存, 正常展开的数据下标不会超过列数
+            path.iter()
+                .all(|s| !matches!(s, Segment::Index(i) if *i >= max_index))
+                && !path_prefixes(&key).any(|prefix| keys.contains(prefix))
+        });
+        match path {
+            Some(path) if insert_path(&mut nested, &path, value.clone()) => {}
+            _ => literal.push((key, value)),
+        }
+    }
+    trim_arrays(&mut nested);
+    if let Value::Object(map) = &mut nested {
+        for (key, value) in literal {
+            map.insert(key, value);
+        }
+    }
+    nested
+}
+
+// "user.tags[0]" -> "user", "user.tags"; 和这些前缀同名的列存在时,
+// 这个路径不能嵌套进去, 否则会覆盖那一列
+fn path_prefixes(key: &str) -> impl Iterator<Item = &str> {
+    key.match_indices(['.', '[']).map(move |(i, _)| &key[..i])
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+// "user.tags[0]" -> [Key("user"), Key("tags"), Index(0)], 像 "No. of goals"
+// 这种带空格的片段不当作路径
+fn parse_path(key: &str) -> Option<Vec<Segment>> {
+    let mut path = Vec::new();
+    for part in key.split('.') {
+        let (name, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if name.is_empty() || name.trim() != name || name.contains(']') {
+            return None;
+        }
+        path.push(Segment::Key(name.to_string()));
+        while !rest.is_empty() {
+            let end = rest.find(']')?;
+            let index = rest.get(1..end)?.parse().ok()?;
+            path.push(Segment::Index(index));
+            rest = &rest[end + 1..];
+            if !rest.is_empty() && !rest.starts_with('[') {
+                return None;
+            }
+        }
+    }
+    Some(path)
+}
+
+// 插入失败(和已有的值冲突)时返回 false, 只会在已有的节点上失败,
+// 新建的节点下一定能插入, 所以失败时不会留下多余的空节点
+fn insert_path(target: &mut Value, path: &[Segment], value: Value) -> bool {
+    let Some((first, rest)) = path.split_first() else {
+        return false;
+    };
+    let empty = || match rest.first() {
+        Some(Segment::Index(_)) => Value::Array(Vec::new()),
+        _ => Value::Object(Map::new()),
+    };
+    let slot = match (first, target) {
+        (Segment::Key(key), Value::Object(map)) => {
+            if rest.is_empty() {
+                if map.contains_key(key) {
+                    return false;
+                }
+                map.insert(key.clone(), value);
+                return true;
+            }
+            map.entry(key.clone()).or_insert_with(empty)
+        }
+        (Segment::Index(i), Value::Array(items)) => {
+            if items.len() <= *i {
+                items.resize(i + 1, Value::Null);
+            }
+            let slot = &mut items[*i];
+            if rest.is_empty() {
+                if !slot.is_null() {
+                    return false;
+                }
+                *slot = value;
+                return true;
+            }
+            if slot.is_null() {
+                *slot = empty();
+            }
+            slot
+        }
+        _ => return false,
+    };
+    insert_path(slot, rest, value)
+}
+
+fn trim_arrays(value: &mut Value) {
+    match value {
+        Value::Object(map) => map.values_mut().for_each(trim_arrays),
+        Value::Array(items) => {
+            // 空单元格在字符串列里是 "", 其他列里是 null
+            while items.last().is_some_and(|v| v.is_null() || v == "") {
+                items.pop();
+            }
+            items.iter_mut().for_each(trim_arrays);
+        }
+        _ => {}
+    }
+}
+
+// 把每个数组展开成多行, 同一行里有多个数组时取笛卡尔积
+fn explode(value: Value) -> Vec<Value> {
+    match value {
+        Value::Object(map) => {
+            let mut rows = vec![Map::new()];
+            for (key, v) in map {
+                let alternatives = explode(v);
+                let mut next = Vec::with_capacity(rows.len() * alternatives.len());
+                for row in rows {
+                    for alt in &alternatives {
+                        let mut row = row.clone();
+                        row.insert(key.clone(), alt.clone());
+                        next.push(row);
+                    }
+                }
+                rows = next;
+            }
+            rows.into_iter().map(Value::Object).collect()
+        }
+        Value::Array(items) if items.is_empty() => vec![Value::Null],
+        Value::Array(items) => items.into_iter().flat_map(explode).collect(),
+        v => vec![v],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_flatten_nested_objects() {
+        let rows = vec![
+            json!({"name": "Perin", "address": {"city": "Turin", "zip": "10100"}}),
+            json!({"name": "Buffon", "team": {"name": "Juventus"}, "tags": ["gk", "captain"]}),
+        ];
+        let (headers, rows) = flatten_rows(rows, &CsvFlattenOpts::default());
+        assert_eq!(
+            headers,
+            ["name", "address.city", "address.zip", "team.name", "tags"]
+        );
+        assert_eq!(rows[0]["address.city"], json!("Turin"));
+        assert_eq!(rows[1]["tags"], json!(["gk", "captain"]));
+    }
+
+    #[test]
+    fn test_flatten_explode_arrays() {
+        let rows = vec![json!({"name": "Buffon", "tags": ["gk", "captain"], "kit": [1, 77]})];
+        let opts = CsvFlattenOpts {
+            arrays: ArrayMode::Explode,
+            ..Default::default()
+        };
+        let (headers, rows) = flatten_rows(rows, &opts);
+        assert_eq!(headers, ["name", "tags", "kit"]);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[3]["tags"], json!("captain"));
+        assert_eq!(rows[3]["kit"], json!(77));
+    }
+
+    #[test]
+    fn test_flatten_indexed_arrays() {
+        let rows = vec![json!({"name": "Buffon", "tags": ["gk", "captain"], "clubs": []})];
+        let opts = CsvFlattenOpts {
+            arrays: ArrayMode::Index,
+            ..Default::default()
+        };
+        let (headers, _) = flatten_rows(rows, &opts);
+        assert_eq!(headers, ["name", "tags[0]", "tags[1]"]);
+    }
+
+    #[test]
+    fn test_unflatten_row() {
+        let row = json!({
+            "id": 1,
+            "user.name": "Perin",
+            "user.address.city": "Turin",
+            "tags[0]": "gk",
+            "tags[1]": null,
+            "kits[0].number": 37,
+            "No. of goals": 0,
+        });
+        let Value::Object(row) = row else {
+            unreachable!()
+        };
+        assert_eq!(
+            unflatten_row(row),
+            json!({
+                "id": 1,
+                "user": {"name": "Perin", "address": {"city": "Turin"}},
+                "tags": ["gk"],
+                "kits": [{"number": 37}],
+                "No. of goals": 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_unflatten_keeps_clashing_keys() {
+        let Value::Object(row) = json!({"user": "Perin", "user.name": "Buffon"}) else {
+            unreachable!()
+        };
+        assert_eq!(
+            unflatten_row(row),
+            json!({"user": "Perin", "user.name": "Buffon"})
+        );
+        let Value::Object(row) = json!({"user.name": "Buffon", "tags[0]": "gk", "user": "Perin"})
+        else {
+            unreachable!()
+        };
+        assert_eq!(
+            unflatten_row(row),
+            json!({"tags": ["gk"], "user": "Perin", "user.name": "Buffon"})
+        );
+        assert_eq!(parse_path("tags[x]"), None);
+        assert_eq!(
+            parse_path("a[0][1]"),
+            Some(vec![
+                Segment::Key("a".into()),
+                Segment::Index(0),
+                Segment::Index(1)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unflatten_large_index_is_literal() {
+        let Value::Object(row) = json!({"tags[1000000000]": "gk", "kits[1]": 37}) else {
+            unreachable!()
+        };
+        assert_eq!(
+            unflatten_row(row),
+            json!({"kits": [null, 37], "tags[1000000000]": "gk"})
+        );
+    }
+
+    #[test]
+    fn test_read_ndjson() -> Result<()> {
+        let rows = read_structured("fixtures/players.ndjson", InputFormat::Ndjson)?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["address"]["city"], json!("Turin"));
+        Ok(())
+    }
+}
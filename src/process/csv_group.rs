@@ -0,0 +1,494 @@
+use super::csv_filter::compare_values;
+use super::csv_reader::open_csv;
+use super::csv_types::TypedRows;
+use super::csv_writer::row_writer;
+use crate::{CsvOutputOpts, CsvReaderOpts, CsvTypeOpts};
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// An aggregate over the rows of a group, e.g. `count(*)` or `avg(Kit Number)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aggregate {
+    pub func: AggFunc,
+    /// `None` for `count(*)`
+    pub column: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AggFunc {
+    /// Rows, or non-empty cells of a column
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+#[derive(Debug, Clone)]
+enum Acc {
+    Count(u64),
+    Sum { total: f64, integral: bool, n: u64 },
+    Min(Option<Value>),
+    Max(Option<Value>),
+    Avg { total: f64, n: u64 },
+}
+
+/// Group rows by the `by` columns and compute `aggs` for every group.
+///
+/// Groups are kept in a hash table, memory grows with the number of groups
+/// rather than rows. Groups come out in first-seen order.
+pub fn process_csv_group(
+    input: &str,
+    by: &[String],
+    aggs: &[Aggregate],
+    opts: &CsvReaderOpts,
+    type_opts: &CsvTypeOpts,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let (reader, headers) = open_csv(input, opts)?;
+    let rows = TypedRows::new(reader, headers, type_opts)?;
+    let names: Vec<String> = rows.headers().iter().map(String::from).collect();
+    check_columns(
+        &names,
+        by.iter()
+            .chain(aggs.iter().filter_map(|a| a.column.as_ref())),
+    )?;
+
+    let mut index: HashMap<Vec<String>, usize> = HashMap::new();
+    let mut groups: Vec<(Vec<Value>, Vec<Acc>)> = Vec::new();
+    for (i, row) in rows.enumerate() {
+        let row = row?;
+        let values: Vec<Value> = by.iter().map(|c| cell(&row, c)).collect();
+        let slot = *index.entry(key(&values)).or_insert_with(|| {
+            groups.push((values, aggs.iter().map(|a| Acc::new(a.func)).collect()));
+            groups.len() - 1
+        });
+        for (acc, agg) in groups[slot].1.iter_mut().zip(aggs) {
+            acc.add(agg.column.as_ref().map(|c| cell(&row, c)).as_ref())
+                .map_err(|e| anyhow!("row {}: {}: {}", i + 1, agg, e))?;
+        }
+    }
+
+    let mut columns = by.to_vec();
+    columns.extend(aggs.iter().map(|a| a.to_string()));
+    let (file, output) = out.create()?;
+    let mut writer = row_writer(output, out.format(), &columns, opts)?;
+    for (values, accs) in groups {
+        let row: Map<String, Value> = columns
+            .iter()
+            .cloned()
+            .zip(values.into_iter().chain(accs.into_iter().map(Acc::finish)))
+            .collect();
+        writer.write_row(&Value::Object(row))?;
+    }
+    writer.finish()?;
+    file.commit()
+}
+
+/// Cross-tabulate: one row per distinct `rows` key, one column per distinct
+/// value of `cols` (sorted), each cell is `value` over the matching rows.
+/// Blank values are named `(empty)`, a name already in use gets ` (2)`, ...
+pub fn process_csv_pivot(
+    input: &str,
+    rows: &[String],
+    cols: &str,
+    value: &Aggregate,
+    opts: &CsvReaderOpts,
+    type_opts: &CsvTypeOpts,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let (reader, headers) = open_csv(input, opts)?;
+    let records = TypedRows::new(reader, headers, type_opts)?;
+    let names: Vec<String> = records.headers().iter().map(String::from).collect();
+    let cols = cols.to_string();
+    check_columns(
+        &names,
+        rows.iter()
+            .chain(std::iter::once(&cols))
+            .chain(value.column.as_ref()),
+    )?;
+
+    let mut row_index: HashMap<Vec<String>, usize> = HashMap::new();
+    let mut row_keys: Vec<Vec<Value>> = Vec::new();
+    let mut col_index: HashMap<String, usize> = HashMap::new();
+    let mut col_values: Vec<Value> = Vec::new();
+    let mut cells: HashMap<(usize, usize), Acc> = HashMap::new();
+    for (i, row) in records.enumerate() {
+        let row = row?;
+        let values: Vec<Value> = rows.iter().map(|c| cell(&row, c)).collect();
+        let r = *row_index.entry(key(&values)).or_insert_with(|| {
+            row_keys.push(values);
+            row_keys.len() - 1
+        });
+        let col = cell(&row, &cols);
+        let c = *col_index.entry(text(&col)).or_insert_with(|| {
+            col_values.push(col);
+            col_values.len() - 1
+        });
+        cells
+            .entry((r, c))
+            .or_insert_with(|| Acc::new(value.func))
+            .add(value.column.as_ref().map(|c| cell(&row, c)).as_ref())
+            .map_err(|e| anyhow!("row {}: {}: {}", i + 1, value, e))?;
+    }
+
+    let mut order: Vec<usize> = (0..col_values.len()).collect();
+    order.sort_by(|&a, &b| {
+        compare_values(&col_values[a], &col_values[b]).unwrap_or(Ordering::Equal)
+    });
+    // 列值可能和行键的列名或者 "(empty)" 重名, 重名的加上 " (2)", " (3)", ...
+    // 否则 Map 里后一列会覆盖前一列
+    let mut columns = rows.to_vec();
+    let mut taken: HashSet<String> = columns.iter().cloned().collect();
+    for &c in &order {
+        let base = match text(&col_values[c]) {
+            name if name.is_empty() => "(empty)".to_string(),
+            name => name,
+        };
+        let mut name = base.clone();
+        let mut n = 1;
+        while !taken.insert(name.clone()) {
+            n += 1;
+            name = format!("{} ({})", base, n);
+        }
+        columns.push(name);
+    }
+    let (file, output) = out.create()?;
+    let mut writer = row_writer(output, out.format(), &columns, opts)?;
+    for (r, values) in row_keys.into_iter().enumerate() {
+        // 没有对应行的格子: count 为 0, 其他为空
+        let totals = order.iter().map(|&c| match cells.remove(&(r, c)) {
+            Some(acc) => acc.finish(),
+            None => Acc::new(value.func).finish(),
+        });
+        let row: Map<String, Value> = columns
+            .iter()
+            .cloned()
+            .zip(values.into_iter().chain(totals))
+            .collect();
+        writer.write_row(&Value::Object(row))?;
+    }
+    writer.finish()?;
+    file.commit()
+}
+
+fn check_columns<'a>(
+    headers: &[String],
+    columns: impl IntoIterator<Item = &'a String>,
+) -> Result<()> {
+    for column in columns {
+        if !headers.contains(column) {
+            return Err(anyhow!("Unknown column {:?}", column));
+        }
+    }
+    Ok(())
+}
+
+fn cell(row: &Map<String, Value>, column: &str) -> Value {
+    row.get(column).cloned().unwrap_or(Value::Null)
+}
+
+fn text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+fn key(values: &[Value]) -> Vec<String> {
+    values.iter().map(text).collect()
+}
+
+fn is_empty(v: &Value) -> bool {
+    match v {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+fn as_number(v: &Value) -> Result<f64> {
+    match v {
+        Value::Number(n) => n.as_f64().ok_or_else(|| anyhow!("{} is out of range", n)),
+        Value::String(s) => s
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("{:?} is not a number", s)),
+        v => Err(anyhow!("{} is not a number", v)),
+    }
+}
+
+impl Acc {
+    fn new(func: AggFunc) -> Self {
+        match func {
+            AggFunc::Count => Acc::Count(0),
+            AggFunc::Sum => Acc::Sum {
+                total: 0.0,
+                integral: true,
+                n: 0,
+            },
+            AggFunc::Min => Acc::Min(None),
+            AggFunc::Max => Acc::Max(None),
+            AggFunc::Avg => Acc::Avg { total: 0.0, n: 0 },
+        }
+    }
+
+    /// Add a cell, `None` for `count(*)`. Empty cells are skipped.
+    fn add(&mut self, value: Option<&Value>) -> Result<()> {
+        let value = match (self, value) {
+            (Acc::Count(n), None) => {
+                *n += 1;
+                return Ok(());
+            }
+            (_, Some(v)) if is_empty(v) => return Ok(()),
+            (acc, Some(v)) => (acc, v),
+            (_, None) => return Ok(()),
+        };
+        match value {
+            (Acc::Count(n), _) => *n += 1,
+            (Acc::Sum { total, integral, n }, v) => {
+                *total += as_number(v)?;
+                *integral &= v.is_i64() || v.is_u64();
+                *n += 1;
+            }
+            (Acc::Avg { total, n }, v) => {
+                *total += as_number(v)?;
+                *n += 1;
+            }
+            (Acc::Min(min), v) => {
+                if min
+                    .as_ref()
+                    .is_none_or(|m| compare_values(v, m) == Some(Ordering::Less))
+                {
+                    *min = Some(v.clone());
+                }
+            }
+            (Acc::Max(max), v) => {
+                if max
+                    .as_ref()
+                    .is_none_or(|m| compare_values(v, m) == Some(Ordering::Greater))
+                {
+                    *max = Some(v.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            Acc::Count(n) => Value::from(n),
+            Acc::Sum { n: 0, .. } | Acc::Avg { n: 0, .. } => Value::Null,
+            // 全是整数时结果也保持整数
+            Acc::Sum {
+                total,
+                integral: true,
+                ..
+            } if total.abs() < 9e15 => Value::from(total as i64),
+            Acc::Sum { total, .. } => Value::from(total),
+            Acc::Avg { total, n } => Value::from(total / n as f64),
+            Acc::Min(v) | Acc::Max(v) => v.unwrap_or(Value::Null),
+        }
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = anyhow::Error;
+
+    /// `count(*)`, `count(Name)`, `avg(Kit Number)`, or a bare `count`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (func, column) = match s.strip_suffix(')').and_then(|s| s.split_once('(')) {
+            Some((func, column)) => (func.trim(), Some(column.trim())),
+            None => (s, None),
+        };
+        let func: AggFunc = func.parse()?;
+        let column = match column {
+            None | Some("*") if func == AggFunc::Count => None,
+            None | Some("*") | Some("") => {
+                return Err(anyhow!(
+                    "{} needs a column, e.g. {}(Kit Number)",
+                    func,
+                    func
+                ))
+            }
+            Some(column) => Some(column.to_string()),
+        };
+        Ok(Aggregate { func, column })
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}({})",
+            self.func,
+            self.column.as_deref().unwrap_or("*")
+        )
+    }
+}
+
+impl FromStr for AggFunc {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "count" => Ok(AggFunc::Count),
+            "sum" => Ok(AggFunc::Sum),
+            "min" => Ok(AggFunc::Min),
+            "max" => Ok(AggFunc::Max),
+            "avg" | "mean" => Ok(AggFunc::Avg),
+            _ => Err(anyhow!("Invalid aggregate function: {}", s)),
+        }
+    }
+}
+
+impl From<AggFunc> for &'static str {
+    fn from(value: AggFunc) -> Self {
+        match value {
+            AggFunc::Count => "count",
+            AggFunc::Sum => "sum",
+            AggFunc::Min => "min",
+            AggFunc::Max => "max",
+            AggFunc::Avg => "avg",
+        }
+    }
+}
+
+impl Display for AggFunc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OutputFormat;
+    use std::fs;
+
+    fn ndjson(dir: &tempfile::TempDir) -> CsvOutputOpts {
+        CsvOutputOpts {
+            output: Some(dir.path().join("out.ndjson").to_string_lossy().into_owned()),
+            format: Some(OutputFormat::Ndjson),
+            ..Default::default()
+        }
+    }
+
+    fn read(out: &CsvOutputOpts) -> Result<Vec<Value>> {
+        let content = fs::read_to_string(out.output.as_ref().unwrap())?;
+        Ok(content
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?)
+    }
+
+    #[test]
+    fn test_parse_aggregate() -> Result<()> {
+        let count: Aggregate = "count".parse()?;
+        assert_eq!(count.to_string(), "count(*)");
+        let avg: Aggregate = " AVG(Kit Number) ".parse()?;
+        assert_eq!(avg.func, AggFunc::Avg);
+        assert_eq!(avg.column.as_deref(), Some("Kit Number"));
+        assert!("sum(*)".parse::<Aggregate>().is_err());
+        assert!("median(DOB)".parse::<Aggregate>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_group() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out = ndjson(&dir);
+        let aggs = ["count(*)", "min(DOB)", "avg(Kit Number)", "sum(Kit Number)"]
+            .iter()
+            .map(|s| s.parse())
+            .collect::<Result<Vec<Aggregate>>>()?;
+        process_csv_group(
+            "assets/juventus.csv",
+            &["Position".to_string()],
+            &aggs,
+            &Default::default(),
+            &Default::default(),
+            &out,
+        )?;
+        let rows = read(&out)?;
+        assert_eq!(rows.len(), 10);
+        assert_eq!(
+            rows[0],
+            serde_json::json!({
+                "Position": "Goalkeeper",
+                "count(*)": 4,
+                "min(DOB)": "Apr 18, 1990 (29)",
+                "avg(Kit Number)": 36.5,
+                "sum(Kit Number)": 146,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pivot() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out = ndjson(&dir);
+        process_csv_pivot(
+            "assets/juventus.csv",
+            &["Nationality".to_string()],
+            "Position",
+            &"count".parse()?,
+            &Default::default(),
+            &Default::default(),
+            &out,
+        )?;
+        let rows = read(&out)?;
+        let italy = rows.iter().find(|r| r["Nationality"] == "Italy").unwrap();
+        assert_eq!(italy["Goalkeeper"], 3);
+        assert_eq!(italy["Second Striker"], 0);
+        let Value::Object(first) = &rows[0] else {
+            panic!("expect an object");
+        };
+        // 第一列是行键, 后面的列按值排序
+        assert_eq!(
+            first.keys().take(3).collect::<Vec<_>>(),
+            ["Nationality", "Central Midfield", "Centre-Back"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pivot_clashing_names() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("squad.csv");
+        fs::write(
+            &input,
+            "Team,Position\nA,Team\nA,\nA,(empty)\nB,Goalkeeper\n",
+        )?;
+        let out = ndjson(&dir);
+        process_csv_pivot(
+            &input.to_string_lossy(),
+            &["Team".to_string()],
+            "Position",
+            &"count".parse()?,
+            &Default::default(),
+            &Default::default(),
+            &out,
+        )?;
+        let rows = read(&out)?;
+        let Value::Object(first) = &rows[0] else {
+            panic!("expect an object");
+        };
+        assert_eq!(
+            first.keys().collect::<Vec<_>>(),
+            ["Team", "(empty)", "(empty) (2)", "Goalkeeper", "Team (2)"]
+        );
+        assert_eq!(first["Team"], "A");
+        assert_eq!(first["Team (2)"], 1);
+        assert_eq!(first["(empty) (2)"], 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+use super::csv_reader::{open_csv, CsvReader};
+use super::csv_sort::{key_indices, SortedRecords};
+use super::csv_writer::{row_writer, RowWriter};
+use crate::{CsvOutputOpts, CsvReaderOpts, JoinType};
+use anyhow::Result;
+use csv::StringRecord;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::path::Path;
+
+type KeyedRow = (Vec<String>, Vec<String>);
+
+/// Join two csv files on the `on` columns.
+///
+/// Both sides are sorted externally on the key and merged, so rows come out
+/// in key order and neither file has to fit in memory. Right columns whose
+/// names clash with left ones are prefixed with the right file stem, and
+/// suffixed with `_2`, `_3`, ... if that name is taken too.
+pub fn process_csv_join(
+    left: &str,
+    right: &str,
+    on: &[String],
+    how: JoinType,
+    buffer_rows: usize,
+    opts: &CsvReaderOpts,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let (left_reader, left_headers) = open_csv(left, opts)?;
+    let (right_reader, right_headers) = open_csv(right, opts)?;
+    let left_key = key_indices(&left_headers, on, left)?;
+    let right_key = key_indices(&right_headers, on, right)?;
+
+    // 右表的 key 列和左表重复, 只保留左表的
+    let right_columns: Vec<usize> = (0..right_headers.len())
+        .filter(|i| !right_key.contains(i))
+        .collect();
+    let stem = Path::new(right)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "right".to_string());
+    let mut headers: Vec<String> = left_headers.iter().map(String::from).collect();
+    for &i in &right_columns {
+        let mut name = right_headers[i].to_string();
+        // 加了前缀的列名也可能已经存在, 再加上 _2, _3, ... 直到不重名
+        if headers.contains(&name) {
+            let base = format!("{}.{}", stem, name);
+            name = base.clone();
+            let mut n = 1;
+            while headers.contains(&name) {
+                n += 1;
+                name = format!("{}_{}", base, n);
+            }
+        }
+        headers.push(name);
+    }
+
+    let (file, output) = out.create()?;
+    let mut writer = row_writer(output, out.format(), &headers, opts)?;
+    let mut emit = |left_row: Option<&[String]>, key: &[String], right_row: Option<&[String]>| {
+        let mut row = Map::new();
+        for (i, name) in left_headers.iter().enumerate() {
+            let value = match (left_row, left_key.iter().position(|&k| k == i)) {
+                (Some(r), _) => cell(r, i),
+                // 只有右表有这一行时, key 列从右表补上
+                (None, Some(k)) => Value::String(key[k].clone()),
+                (None, None) => Value::Null,
+            };
+            row.insert(name.to_string(), value);
+        }
+        for (&i, name) in right_columns.iter().zip(&headers[left_headers.len()..]) {
+            let value = right_row.map(|r| cell(r, i)).unwrap_or(Value::Null);
+            row.insert(name.clone(), value);
+        }
+        writer.write_row(&Value::Object(row))
+    };
+
+    let mut lefts = SortedRecords::new(left_reader, left_key.clone(), buffer_rows)?;
+    let mut rights = SortedRecords::new(right_reader, right_key, buffer_rows)?;
+    let mut l = lefts.next().transpose()?;
+    let mut r = rights.next().transpose()?;
+    loop {
+        let ord = match (&l, &r) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some((a, _)), Some((b, _))) => a.cmp(b),
+        };
+        match ord {
+            Ordering::Less => {
+                let (key, row) = l.take().unwrap_or_default();
+                if how != JoinType::Inner {
+                    emit(Some(&row), &key, None)?;
+                }
+                l = lefts.next().transpose()?;
+            }
+            Ordering::Greater => {
+                let (key, row) = r.take().unwrap_or_default();
+                if how == JoinType::Outer {
+                    emit(None, &key, Some(&row))?;
+                }
+                r = rights.next().transpose()?;
+            }
+            Ordering::Equal => {
+                // 相同 key 的右表行放进内存, 和每一条相同 key 的左表行配对
+                let (key, first) = r.take().unwrap_or_default();
+                let mut group = vec![first];
+                r = next_in_group(&mut rights, &key, &mut group)?;
+                while let Some((k, row)) = l.take() {
+                    if k != key {
+                        l = Some((k, row));
+                        break;
+                    }
+                    for right_row in &group {
+                        emit(Some(&row), &key, Some(right_row))?;
+                    }
+                    l = lefts.next().transpose()?;
+                }
+            }
+        }
+    }
+    writer.finish()?;
+    file.commit()
+}
+
+/// Concatenate csv files, the header is the union of all headers in
+/// first-seen order (or only the columns every file has with `intersect`)
+pub fn process_csv_cat(
+    inputs: &[String],
+    intersect: bool,
+    opts: &CsvReaderOpts,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let readers = inputs
+        .iter()
+        .map(|input| open_csv(input, opts))
+        .collect::<Result<Vec<_>>>()?;
+    let mut headers: Vec<String> = Vec::new();
+    for (_, h) in &readers {
+        for name in h {
+            if !headers.iter().any(|x| x == name) {
+                headers.push(name.to_string());
+            }
+        }
+    }
+    if intersect {
+        headers.retain(|name| readers.iter().all(|(_, h)| h.iter().any(|x| x == name)));
+    }
+
+    let (file, output) = out.create()?;
+    let mut writer = row_writer(output, out.format(), &headers, opts)?;
+    for (reader, file_headers) in readers {
+        copy_rows(reader, &file_headers, &headers, writer.as_mut(), |_| true)?;
+    }
+    writer.finish()?;
+    file.commit()
+}
+
+/// Drop rows whose `by` columns (or whole row when empty) were already seen,
+/// the first occurrence is kept and the order is preserved
+pub fn process_csv_dedup(
+    input: &str,
+    by: &[String],
+    opts: &CsvReaderOpts,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let (reader, file_headers) = open_csv(input, opts)?;
+    let key = if by.is_empty() {
+        (0..file_headers.len()).collect()
+    } else {
+        key_indices(&file_headers, by, input)?
+    };
+    let headers: Vec<String> = file_headers.iter().map(String::from).collect();
+    let (file, output) = out.create()?;
+    let mut writer = row_writer(output, out.format(), &headers, opts)?;
+    let mut seen = HashSet::new();
+    copy_rows(reader, &file_headers, &headers, writer.as_mut(), |record| {
+        let k: Vec<String> = key
+            .iter()
+            .map(|&i| record.get(i).unwrap_or_default().to_string())
+            .collect();
+        seen.insert(k)
+    })?;
+    writer.finish()?;
+    file.commit()
+}
+
+// 把 reader 里满足 keep 的行按 headers 的列顺序写出, 缺少的列为空
+fn copy_rows(
+    mut reader: CsvReader,
+    file_headers: &StringRecord,
+    headers: &[String],
+    writer: &mut dyn RowWriter,
+    mut keep: impl FnMut(&StringRecord) -> bool,
+) -> Result<()> {
+    let positions: Vec<Option<usize>> = headers
+        .iter()
+        .map(|h| file_headers.iter().position(|x| x == h))
+        .collect();
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        if !keep(&record) {
+            continue;
+        }
+        let row: Map<String, Value> = headers
+            .iter()
+            .zip(&positions)
+            .map(|(name, pos)| {
+                let value = pos
+                    .and_then(|i| record.get(i))
+                    .map(|s| Value::String(s.to_string()))
+                    .unwrap_or(Value::Null);
+                (name.clone(), value)
+            })
+            .collect();
+        writer.write_row(&Value::Object(row))?;
+    }
+    Ok(())
+}
+
+fn next_in_group(
+    rows: &mut SortedRecords,
+    key: &[String],
+    group: &mut Vec<Vec<String>>,
+) -> Result<Option<KeyedRow>> {
+    for next in rows.by_ref() {
+        let (k, row) = next?;
+        if k != key {
+            return Ok(Some((k, row)));
+        }
+        group.push(row);
+    }
+    Ok(None)
+}
+
+fn cell(row: &[String], i: usize) -> Value {
+    row.get(i)
+        .map(|s| Value::String(s.clone()))
+        .unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_SORT_BUFFER;
+    use std::fs;
+
+    fn output(dir: &tempfile::TempDir) -> CsvOutputOpts {
+        CsvOutputOpts {
+            output: Some(dir.path().join("out.csv").to_string_lossy().into_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn read_output(out: &CsvOutputOpts) -> Result<Vec<String>> {
+        let content = fs::read_to_string(out.output.as_ref().unwrap())?;
+        Ok(content.lines().map(String::from).collect())
+    }
+
+    #[test]
+    fn test_join() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out = output(&dir);
+        let join = |how, buffer_rows| -> Result<Vec<String>> {
+            process_csv_join(
+                "fixtures/teams.csv",
+                "fixtures/players.csv",
+                &["team_id".to_string()],
+                how,
+                buffer_rows,
+                &Default::default(),
+                &out,
+            )?;
+            read_output(&out)
+        };
+
+        let inner = join(JoinType::Inner, DEFAULT_SORT_BUFFER)?;
+        assert_eq!(inner[0], "team_id,name,players.name,position");
+        assert_eq!(
+            &inner[1..],
+            [
+                "1,Juventus,Paulo Dybala,Second Striker",
+                "1,Juventus,Gianluigi Buffon,Goalkeeper",
+                "1,Juventus,Paulo Dybala,Second Striker",
+                "2,Inter,Samir Handanovic,Goalkeeper",
+            ]
+        );
+        let left = join(JoinType::Left, DEFAULT_SORT_BUFFER)?;
+        assert_eq!(left.last().unwrap(), "3,Milan,,");
+        let outer = join(JoinType::Outer, 1)?;
+        assert_eq!(outer.len(), 7);
+        assert_eq!(outer.last().unwrap(), "4,,Francesco Totti,Forward");
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_renamed_column_clash() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out = output(&dir);
+        let left = dir.path().join("teams.csv");
+        fs::write(
+            &left,
+            "team_id,name,players.name,players.name_2\n2,Inter,x,y\n",
+        )?;
+        process_csv_join(
+            &left.to_string_lossy(),
+            "fixtures/players.csv",
+            &["team_id".to_string()],
+            JoinType::Inner,
+            DEFAULT_SORT_BUFFER,
+            &Default::default(),
+            &out,
+        )?;
+        let lines = read_output(&out)?;
+        assert_eq!(
+            lines[0],
+            "team_id,name,players.name,players.name_2,players.name_3,position"
+        );
+        assert_eq!(lines[1], "2,Inter,x,y,Samir Handanovic,Goalkeeper");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_reconciles_headers() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out = output(&dir);
+        let inputs = [
+            "fixtures/teams.csv".to_string(),
+            "fixtures/players.csv".to_string(),
+        ];
+        process_csv_cat(&inputs, false, &Default::default(), &out)?;
+        let lines = read_output(&out)?;
+        assert_eq!(lines[0], "team_id,name,position");
+        assert_eq!(lines[1], "1,Juventus,");
+        assert_eq!(lines[4], "1,Paulo Dybala,Second Striker");
+
+        process_csv_cat(&inputs, true, &Default::default(), &out)?;
+        assert_eq!(read_output(&out)?[0], "team_id,name");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out = output(&dir);
+        process_csv_dedup(
+            "fixtures/players.csv",
+            &["position".to_string()],
+            &Default::default(),
+            &out,
+        )?;
+        let lines = read_output(&out)?;
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "1,Paulo Dybala,Second Striker");
+
+        process_csv_dedup("fixtures/players.csv", &[], &Default::default(), &out)?;
+        assert_eq!(read_output(&out)?.len(), 5);
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use super::csv_reader::open_csv;
+use super::csv_show::render_table;
+use super::csv_types::{convert_record, pinned_columns, resolve_column_types};
+use super::csv_writer::{cell_to_string, row_writer};
+use crate::{ColumnType, CsvReaderOpts, CsvTypeOpts, OutputFormat};
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use rusqlite::types::ValueRef;
+use rusqlite::{params_from_iter, Connection};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+/// Column names and rows returned by a query
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    pub headers: Vec<String>,
+    pub rows: Vec<Map<String, Value>>,
+}
+
+/// Load every input into an in-memory SQLite database, one table per file
+/// named after its stem, and run `sql` against it. Result columns with the
+/// same name (`SELECT *` over a join) are renamed `Name_2`, `Name_3`, ...
+pub fn process_csv_query(
+    sql: &str,
+    inputs: &[String],
+    opts: &CsvReaderOpts,
+    type_opts: &CsvTypeOpts,
+) -> Result<QueryResult> {
+    let mut conn = Connection::open_in_memory()?;
+    let mut tables = HashSet::new();
+    for input in inputs {
+        let table = table_name(input);
+        if !tables.insert(table.clone()) {
+            return Err(anyhow!("Duplicate table name {:?} from {}", table, input));
+        }
+        load_table(&mut conn, &table, input, opts, type_opts)?;
+    }
+
+    let mut stmt = conn.prepare(sql)?;
+    // 行是按列名索引的 Map, 重名的列不改名的话后一列会覆盖前一列
+    let mut taken = HashSet::new();
+    let headers: Vec<String> = stmt
+        .column_names()
+        .into_iter()
+        .map(|base| {
+            let mut name = base.to_string();
+            let mut n = 1;
+            while !taken.insert(name.clone()) {
+                n += 1;
+                name = format!("{}_{}", base, n);
+            }
+            name
+        })
+        .collect();
+    let mut rows = Vec::new();
+    let mut result = stmt.query([])?;
+    while let Some(row) = result.next()? {
+        let mut map = Map::new();
+        for (i, name) in headers.iter().enumerate() {
+            map.insert(name.clone(), sql_to_json(row.get_ref(i)?));
+        }
+        rows.push(map);
+    }
+    Ok(QueryResult { headers, rows })
+}
+
+/// Write query results with `format`, or as a table when no format is given
+pub fn write_query_result<W: Write>(
+    mut writer: W,
+    format: Option<OutputFormat>,
+    result: QueryResult,
+    opts: &CsvReaderOpts,
+    width: Option<usize>,
+) -> Result<()> {
+    let QueryResult { headers, rows } = result;
+    let Some(format) = format else {
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                headers
+                    .iter()
+                    .map(|h| {
+                        row.get(h)
+                            .map(|v| cell_to_string(v, ";"))
+                            .unwrap_or_default()
+                    })
+                    .collect()
+            })
+            .collect();
+        writer.write_all(render_table(&headers, &cells, width).as_bytes())?;
+        writer.flush()?;
+        return Ok(());
+    };
+    let mut rows_writer = row_writer(writer, format, &headers, opts)?;
+    for row in rows {
+        rows_writer.write_row(&Value::Object(row))?;
+    }
+    rows_writer.finish()
+}
+
+// "assets/juventus.csv" -> "juventus", 标准输入的表名为 stdin
+fn table_name(input: &str) -> String {
+    if input == "-" {
+        return "stdin".to_string();
+    }
+    Path::new(input)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| input.to_string())
+}
+
+fn load_table(
+    conn: &mut Connection,
+    table: &str,
+    input: &str,
+    opts: &CsvReaderOpts,
+    type_opts: &CsvTypeOpts,
+) -> Result<()> {
+    let (mut reader, headers) = open_csv(input, opts)?;
+    let mut sample = Vec::new();
+    let mut buf = StringRecord::new();
+    while sample.len() < type_opts.infer_rows && reader.read_record(&mut buf)? {
+        sample.push(buf.clone());
+    }
+    let mut types = resolve_column_types(&headers, &sample, type_opts)?;
+    let pinned = pinned_columns(&headers, type_opts);
+
+    let columns: Vec<String> = headers
+        .iter()
+        .zip(&types)
+        .map(|(name, ty)| format!("{} {}", quote_ident(name), sql_type(*ty)))
+        .collect();
+    let tx = conn.transaction()?;
+    tx.execute(
+        &format!(
+            "CREATE TABLE {} ({})",
+            quote_ident(table),
+            columns.join(", ")
+        ),
+        [],
+    )?;
+    {
+        let placeholders = vec!["?"; headers.len()].join(", ");
+        let mut insert = tx.prepare(&format!(
+            "INSERT INTO {} VALUES ({})",
+            quote_ident(table),
+            placeholders
+        ))?;
+        let mut rows = 0;
+        let mut sample = sample.into_iter();
+        loop {
+            let record = match sample.next() {
+                Some(record) => record,
+                None if reader.read_record(&mut buf)? => buf.clone(),
+                None => break,
+            };
+            rows += 1;
+            let mut values = convert_record(&headers, &record, &mut types, &pinned)
+                .map_err(|e| anyhow!("{}: row {}: {} (type set with --types)", input, rows, e))?;
+            // flexible 模式下行的长度可能和表头不一致
+            values.resize(headers.len(), Value::Null);
+            insert.execute(params_from_iter(values.iter().map(json_to_sql)))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_type(ty: ColumnType) -> &'static str {
+    match ty {
+        ColumnType::Integer | ColumnType::Boolean => "INTEGER",
+        ColumnType::Float => "REAL",
+        ColumnType::Null | ColumnType::Date | ColumnType::String => "TEXT",
+    }
+}
+
+fn json_to_sql(value: &Value) -> rusqlite::types::Value {
+    use rusqlite::types::Value as Sql;
+    match value {
+        Value::Null => Sql::Null,
+        Value::Bool(b) => Sql::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Sql::Integer(i),
+            None => Sql::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => Sql::Text(s.clone()),
+        v => Sql::Text(v.to_string()),
+    }
+}
+
+fn sql_to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::from(f),
+        ValueRef::Text(s) | ValueRef::Blob(s) => Value::String(String::from_utf8_lossy(s).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_query_group_by() -> Result<()> {
+        let QueryResult { headers, rows } = process_csv_query(
+            "SELECT Nationality, count(*) AS players FROM juventus \
+             GROUP BY Nationality ORDER BY players DESC, Nationality LIMIT 2",
+            &["assets/juventus.csv".to_string()],
+            &Default::default(),
+            &Default::default(),
+        )?;
+        assert_eq!(headers, ["Nationality", "players"]);
+        assert_eq!(rows[0]["Nationality"], json!("Italy"));
+        assert_eq!(rows[0]["players"], json!(8));
+        assert_eq!(rows[1]["players"], json!(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_join_typed_columns() -> Result<()> {
+        let QueryResult { headers, rows } = process_csv_query(
+            "SELECT n.Continent, max(j.\"Kit Number\") AS kit FROM juventus j \
+             JOIN nations n ON n.Nationality = j.Nationality \
+             GROUP BY n.Continent ORDER BY n.Continent",
+            &[
+                "assets/juventus.csv".to_string(),
+                "fixtures/nations.csv".to_string(),
+            ],
+            &Default::default(),
+            &Default::default(),
+        )?;
+        assert_eq!(headers, ["Continent", "kit"]);
+        assert_eq!(rows.len(), 2);
+        // Kit Number 推断为整数, max 按数值而不是字符串比较
+        assert_eq!(rows[0]["kit"], json!(77));
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_join_select_star() -> Result<()> {
+        let QueryResult { headers, rows } = process_csv_query(
+            "SELECT * FROM juventus j JOIN nations n ON n.Nationality = j.Nationality \
+             ORDER BY j.\"Kit Number\" LIMIT 1",
+            &[
+                "assets/juventus.csv".to_string(),
+                "fixtures/nations.csv".to_string(),
+            ],
+            &Default::default(),
+            &Default::default(),
+        )?;
+        assert_eq!(
+            headers,
+            [
+                "Name",
+                "Position",
+                "DOB",
+                "Nationality",
+                "Kit Number",
+                "Nationality_2",
+                "Continent"
+            ]
+        );
+        assert_eq!(rows[0].len(), headers.len());
+        assert_eq!(rows[0]["Nationality"], json!("Poland"));
+        assert_eq!(rows[0]["Nationality_2"], json!("Poland"));
+        assert_eq!(rows[0]["Continent"], json!("Europe"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_query_result() -> Result<()> {
+        let result = process_csv_query(
+            "SELECT Name FROM juventus LIMIT 1",
+            &["assets/juventus.csv".to_string()],
+            &Default::default(),
+            &Default::default(),
+        )?;
+        let mut out = Vec::new();
+        write_query_result(
+            &mut out,
+            Some(OutputFormat::Ndjson),
+            result,
+            &Default::default(),
+            None,
+        )?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "{\"Name\":\"Wojciech Szczesny\"}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_name() {
+        assert_eq!(table_name("assets/juventus.csv"), "juventus");
+        assert_eq!(table_name("-"), "stdin");
+        assert_eq!(quote_ident("Kit \"No\""), "\"Kit \"\"No\"\"\"");
+    }
+}
@@ -0,0 +1,215 @@
+use super::csv_encoding::decode_reader;
+use crate::{get_reader, CsvReaderOpts};
+use anyhow::{anyhow, Result};
+use calamine::{open_workbook_auto, Data, Reader as _};
+use csv::{Reader, ReaderBuilder, StringRecord, Writer};
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+pub type CsvReader = Reader<Box<dyn Read>>;
+
+/// Open `input` (a file or `-` for stdin) as a CSV reader configured by `opts`,
+/// and return it together with the header row.
+///
+/// Without a header row the columns are named `col1`, `col2`, ... and the
+/// first row is still yielded as a record.
+///
+/// Text input is transcoded to utf-8 according to `--encoding`, a BOM is
+/// stripped. Spreadsheets (.xlsx, .xlsm, .xlsb, .xls, .ods) are recognized
+/// by their extension and the sheet selected by `--sheet` is read instead.
+pub fn open_csv(input: &str, opts: &CsvReaderOpts) -> Result<(CsvReader, StringRecord)> {
+    let mut reader = if is_spreadsheet(input) {
+        // 表格先转换成内存中的 csv, 后面的处理就和普通 csv 文件完全一样
+        let data: Box<dyn Read> = Box::new(Cursor::new(read_sheet(input, opts)?));
+        ReaderBuilder::new()
+            .has_headers(opts.header)
+            .flexible(opts.flexible)
+            .from_reader(data)
+    } else {
+        ReaderBuilder::new()
+            .delimiter(opts.delimiter)
+            .has_headers(opts.header)
+            .quote(opts.quote)
+            .double_quote(opts.escape.is_none())
+            .escape(opts.escape)
+            .comment(opts.comment)
+            .flexible(opts.flexible)
+            .from_reader(decode_reader(get_reader(input)?, opts.encoding)?)
+    };
+    let headers = if opts.header {
+        reader.headers()?.clone()
+    } else {
+        // has_headers(false) 时 headers() 返回第一行但不会消费它
+        let len = reader.headers()?.len();
+        (1..=len).map(|i| format!("col{}", i)).collect()
+    };
+    Ok((reader, headers))
+}
+
+fn is_spreadsheet(input: &str) -> bool {
+    let ext = Path::new(input)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    matches!(
+        ext.as_deref(),
+        Some("xlsx" | "xlsm" | "xlsb" | "xls" | "ods")
+    )
+}
+
+// 读出一个 sheet 并写成 csv, 单元格按显示的值转换成字符串
+fn read_sheet(input: &str, opts: &CsvReaderOpts) -> Result<Vec<u8>> {
+    let mut workbook = open_workbook_auto(input)?;
+    let range = match &opts.sheet {
+        Some(name) => workbook.worksheet_range(name).map_err(|e| {
+            anyhow!(
+                "{}: sheet {:?}: {} (available: {})",
+                input,
+                name,
+                e,
+                workbook.sheet_names().join(", ")
+            )
+        })?,
+        None => workbook
+            .worksheet_range_at(0)
+            .ok_or_else(|| anyhow!("{} has no sheets", input))??,
+    };
+    let mut writer = Writer::from_writer(Vec::new());
+    for row in range.rows() {
+        writer.write_record(row.iter().map(cell_to_string))?;
+    }
+    writer.into_inner().map_err(|e| anyhow!("{}", e))
+}
+
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        // 表格里的整数通常以浮点数保存, 37.0 输出为 37
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => (*f as i64).to_string(),
+        Data::DateTime(dt) => match dt.as_datetime() {
+            Some(dt) if dt.format("%H:%M:%S").to_string() == "00:00:00" => {
+                dt.format("%Y-%m-%d").to_string()
+            }
+            Some(dt) => dt.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            None => dt.as_f64().to_string(),
+        },
+        cell => cell.to_string(),
+    }
+}
+
+/// Zip the fields of a record with the headers, extra fields of a flexible
+/// row get generated names so no data is dropped.
+pub fn zip_record<'a, V>(
+    headers: &'a StringRecord,
+    fields: impl IntoIterator<Item = V> + 'a,
+) -> impl Iterator<Item = (String, V)> + 'a {
+    fields.into_iter().enumerate().map(|(i, field)| {
+        let name = match headers.get(i) {
+            Some(name) => name.to_string(),
+            None => format!("col{}", i + 1),
+        };
+        (name, field)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_spreadsheets() -> Result<()> {
+        for input in ["fixtures/players.xlsx", "fixtures/players.ods"] {
+            let (mut reader, headers) = open_csv(input, &CsvReaderOpts::default())?;
+            assert_eq!(headers.len(), 4, "{}", input);
+            let first = reader.records().next().unwrap()?;
+            assert_eq!(
+                first.iter().collect::<Vec<_>>(),
+                ["Wojciech Szczesny", "Goalkeeper", "1990-04-18", "1"],
+                "{}",
+                input
+            );
+
+            let opts = CsvReaderOpts {
+                sheet: Some("Staff".into()),
+                ..Default::default()
+            };
+            let (_, headers) = open_csv(input, &opts)?;
+            assert_eq!(headers.iter().collect::<Vec<_>>(), ["Name", "Role"]);
+
+            let opts = CsvReaderOpts {
+                sheet: Some("Missing".into()),
+                ..Default::default()
+            };
+            assert!(open_csv(input, &opts).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_csv_with_header() -> Result<()> {
+        let (mut reader, headers) = open_csv("assets/juventus.csv", &CsvReaderOpts::default())?;
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            ["Name", "Position", "DOB", "Nationality", "Kit Number"]
+        );
+        let first = reader.records().next().unwrap()?;
+        assert_eq!(&first[0], "Wojciech Szczesny");
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_csv_without_header() -> Result<()> {
+        let opts = CsvReaderOpts {
+            header: false,
+            ..Default::default()
+        };
+        let (mut reader, headers) = open_csv("assets/juventus.csv", &opts)?;
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            ["col1", "col2", "col3", "col4", "col5"]
+        );
+        let first = reader.records().next().unwrap()?;
+        assert_eq!(&first[0], "Name");
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_csv_with_dialect() -> Result<()> {
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            comment: Some(b'#'),
+            ..Default::default()
+        };
+        let (mut reader, headers) = open_csv("fixtures/players_semicolon.csv", &opts)?;
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            ["Name", "Position", "Kit Number"]
+        );
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(&records[0][0], "Szczesny, Wojciech");
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_utf16_csv() -> Result<()> {
+        let (mut reader, headers) = open_csv("fixtures/players_utf16.csv", &Default::default())?;
+        assert_eq!(headers.iter().collect::<Vec<_>>(), ["Name", "Nationality"]);
+        let first = reader.records().next().unwrap()?;
+        assert_eq!(&first[0], "Gonzalo Higuaín");
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_record_flexible() {
+        let headers = StringRecord::from(vec!["a", "b"]);
+        let record = StringRecord::from(vec!["1", "2", "3"]);
+        let pairs: Vec<_> = zip_record(&headers, record.iter()).collect();
+        assert_eq!(
+            pairs,
+            [
+                ("a".to_string(), "1"),
+                ("b".to_string(), "2"),
+                ("col3".to_string(), "3")
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use super::csv_reader::open_csv;
+use super::csv_types::TypedRows;
+use crate::{CsvReaderOpts, CsvRenderOutputOpts, CsvTypeOpts, OutputFile};
+use anyhow::{anyhow, Result};
+use minijinja::value::ValueKind;
+use minijinja::{context, Environment, UndefinedBehavior};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const FILENAME: &str = "<filename>";
+
+/// Render csv rows through a minijinja template.
+///
+/// Every row is rendered on its own with its columns as variables, plus `row`
+/// for names that are not identifiers (`row["Kit Number"]`) and `index`
+/// starting at 1. With `all` the template is rendered once with `rows` and
+/// `headers`. With a `filename` template each row goes to the file it names
+/// under `out_dir` and the paths written are returned, a name that is
+/// absolute or contains `..` is an error.
+pub fn process_csv_render(
+    input: &str,
+    template: &str,
+    all: bool,
+    out: &CsvRenderOutputOpts,
+    opts: &CsvReaderOpts,
+    type_opts: &CsvTypeOpts,
+) -> Result<Vec<PathBuf>> {
+    let name = Path::new(template)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| template.to_string());
+    let mut env = Environment::new();
+    env.set_keep_trailing_newline(true);
+    // 列名拼错时直接报错, 而不是渲染成空字符串
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.add_filter("sql", sql);
+    env.add_filter("slug", slug);
+    env.add_template_owned(name.clone(), fs::read_to_string(template)?)?;
+    let output = out.output.as_deref();
+    let filename = out.filename.as_deref();
+    if let Some(filename) = filename {
+        env.add_template_owned(FILENAME, filename.to_string())?;
+    }
+    let tmpl = env.get_template(&name)?;
+
+    let (reader, headers) = open_csv(input, opts)?;
+    let rows = TypedRows::new(reader, headers, type_opts)?;
+    let headers: Vec<String> = rows.headers().iter().map(String::from).collect();
+    if all {
+        let rows = rows.collect::<Result<Vec<_>>>()?;
+        let (file, mut writer) = OutputFile::create(output, out.no_clobber)?;
+        tmpl.render_captured_to(context! { rows, headers }, &mut writer)?;
+        writer.flush()?;
+        file.commit()?;
+        return Ok(Vec::new());
+    }
+
+    let mut writer = match filename {
+        Some(_) => None,
+        None => Some(OutputFile::create(output, out.no_clobber)?),
+    };
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    for (i, row) in rows.enumerate() {
+        let index = i + 1;
+        let ctx = row_context(row?, index);
+        let row_err = |e: minijinja::Error| anyhow!("row {}: {}", index, e);
+        match writer.as_mut() {
+            Some((_, writer)) => {
+                tmpl.render_captured_to(&ctx, &mut *writer)
+                    .map_err(row_err)?;
+            }
+            None => {
+                let name = env.get_template(FILENAME)?.render(&ctx).map_err(row_err)?;
+                let name = Path::new(name.trim());
+                // 文件名来自单元格的值, 不能让 "../../.bashrc" 这样的值写到目录外面
+                if !name
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+                {
+                    return Err(anyhow!(
+                        "row {}: {} is not a relative path inside --out-dir",
+                        index,
+                        name.display()
+                    ));
+                }
+                let path = Path::new(&out.out_dir).join(name);
+                if !seen.insert(path.clone()) {
+                    return Err(anyhow!(
+                        "row {}: {} was already written by an earlier row",
+                        index,
+                        path.display()
+                    ));
+                }
+                if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                    fs::create_dir_all(dir)?;
+                }
+                let (file, mut writer) =
+                    OutputFile::create(Some(&path.to_string_lossy()), out.no_clobber)?;
+                tmpl.render_captured_to(&ctx, &mut writer)
+                    .map_err(row_err)?;
+                writer.flush()?;
+                file.commit()?;
+                files.push(path);
+            }
+        }
+    }
+    if let Some((file, mut writer)) = writer {
+        writer.flush()?;
+        file.commit()?;
+    }
+    Ok(files)
+}
+
+// 列本身叫 row 或 index 时以列为准
+fn row_context(row: Map<String, Value>, index: usize) -> Value {
+    let mut ctx = row.clone();
+    ctx.entry("row").or_insert(Value::Object(row));
+    ctx.entry("index").or_insert(Value::from(index));
+    Value::Object(ctx)
+}
+
+/// `{{ Name | sql }}`: a sql literal, strings are quoted and null is NULL
+fn sql(value: minijinja::Value) -> String {
+    match value.kind() {
+        ValueKind::Undefined | ValueKind::None => "NULL".to_string(),
+        ValueKind::Bool if value.is_true() => "TRUE".to_string(),
+        ValueKind::Bool => "FALSE".to_string(),
+        ValueKind::Number => value.to_string(),
+        _ => format!("'{}'", value.to_string().replace('\'', "''")),
+    }
+}
+
+/// `{{ Name | slug }}`: lowercase with dashes, safe to use in a file name
+fn slug(value: String) -> String {
+    value
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, all: bool, dir: &tempfile::TempDir) -> Result<String> {
+        let output = dir.path().join("out.txt");
+        process_csv_render(
+            "assets/juventus.csv",
+            template,
+            all,
+            &CsvRenderOutputOpts {
+                output: Some(output.to_string_lossy().into_owned()),
+                ..Default::default()
+            },
+            &Default::default(),
+            &Default::default(),
+        )?;
+        Ok(fs::read_to_string(output)?)
+    }
+
+    #[test]
+    fn test_render_each_row() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let sql = render("fixtures/templates/insert.sql", false, &dir)?;
+        let lines: Vec<_> = sql.lines().collect();
+        assert_eq!(lines.len(), 27);
+        assert_eq!(
+            lines[0],
+            "INSERT INTO players (name, position, kit_number) \
+             VALUES ('Wojciech Szczesny', 'Goalkeeper', 1);"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_all_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let md = render("fixtures/templates/report.md", true, &dir)?;
+        assert!(md.starts_with("# Squad\n\n| Name | Position | DOB | Nationality | Kit Number |\n"));
+        assert!(md.contains("| Paulo Dybala | Second Striker |"));
+        assert!(md.ends_with("27 players\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_file_per_row() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out = CsvRenderOutputOpts {
+            filename: Some("{{ Name | slug }}.toml".to_string()),
+            out_dir: dir.path().to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let files = process_csv_render(
+            "assets/juventus.csv",
+            "fixtures/templates/player.toml",
+            false,
+            &out,
+            &Default::default(),
+            &Default::default(),
+        )?;
+        assert_eq!(files.len(), 27);
+        let dybala = fs::read_to_string(dir.path().join("paulo-dybala.toml"))?;
+        assert_eq!(
+            dybala,
+            "name = \"Paulo Dybala\"\nposition = \"Second Striker\"\nkit = 10\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_file_outside_out_dir() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("players.csv");
+        fs::write(&input, "Name\nPerin\n../../.bashrc\n")?;
+        let template = dir.path().join("player.txt");
+        fs::write(&template, "{{ Name }}\n")?;
+        let out = CsvRenderOutputOpts {
+            filename: Some("{{ Name }}".to_string()),
+            out_dir: dir.path().join("out").to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let err = process_csv_render(
+            &input.to_string_lossy(),
+            &template.to_string_lossy(),
+            false,
+            &out,
+            &Default::default(),
+            &Default::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("row 2:"), "{}", err);
+        assert!(!dir.path().join(".bashrc").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_unknown_column() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let template = dir.path().join("bad.txt");
+        fs::write(&template, "{{ Number }}\n")?;
+        let err = render(&template.to_string_lossy(), false, &dir).unwrap_err();
+        assert!(err.to_string().starts_with("row 1:"), "{}", err);
+        assert_eq!(sql(minijinja::Value::from("O'Neil")), "'O''Neil'");
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use super::csv_reader::open_csv;
+use crate::{ColumnType, CsvReaderOpts};
+use anyhow::Result;
+use std::collections::VecDeque;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+pub fn process_csv_show(
+    input: &str,
+    opts: &CsvReaderOpts,
+    offset: usize,
+    head: Option<usize>,
+    tail: Option<usize>,
+    width: Option<usize>,
+) -> Result<String> {
+    let (mut reader, headers) = open_csv(input, opts)?;
+    let mut records = reader.records().skip(offset);
+    let mut rows = VecDeque::new();
+    match (head, tail) {
+        (Some(n), _) => {
+            for record in records.by_ref().take(n) {
+                rows.push_back(record?);
+            }
+        }
+        // tail 只保留最后 n 行, 不需要把整个文件读进内存
+        (None, Some(n)) => {
+            for record in records {
+                rows.push_back(record?);
+                if rows.len() > n {
+                    rows.pop_front();
+                }
+            }
+        }
+        (None, None) => {
+            for record in records {
+                rows.push_back(record?);
+            }
+        }
+    }
+    let headers: Vec<String> = headers.iter().map(String::from).collect();
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|r| r.iter().map(String::from).collect())
+        .collect();
+    Ok(render_table(&headers, &rows, width))
+}
+
+/// Render rows as a box drawing table. Numeric columns are right aligned and
+/// the widest columns are truncated until the table fits in `max_width`.
+pub fn render_table(headers: &[String], rows: &[Vec<String>], max_width: Option<usize>) -> String {
+    let columns = rows
+        .iter()
+        .map(|r| r.len())
+        .chain([headers.len()])
+        .max()
+        .unwrap_or_default();
+    if columns == 0 {
+        return String::new();
+    }
+    let cell = |row: &[String], i: usize| row.get(i).map(|s| clean(s)).unwrap_or_default();
+    let header: Vec<String> = (0..columns).map(|i| cell(headers, i)).collect();
+    let body: Vec<Vec<String>> = rows
+        .iter()
+        .map(|r| (0..columns).map(|i| cell(r, i)).collect())
+        .collect();
+
+    let mut widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            body.iter()
+                .map(|r| r[i].width())
+                .chain([header[i].width()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    if let Some(max_width) = max_width {
+        // 每列两侧各一个空格加一条竖线, 再加上最右边的竖线
+        let budget = max_width.saturating_sub(3 * columns + 1).max(columns);
+        while widths.iter().sum::<usize>() > budget {
+            let widest = (0..columns).max_by_key(|&i| widths[i]).unwrap_or_default();
+            if widths[widest] <= 1 {
+                break;
+            }
+            widths[widest] -= 1;
+        }
+    }
+    let numeric: Vec<bool> = (0..columns)
+        .map(|i| {
+            let mut cells = body.iter().map(|r| r[i].as_str()).filter(|s| !s.is_empty());
+            let mut any = false;
+            let all = cells.all(|s| {
+                any = true;
+                matches!(
+                    ColumnType::infer(s, false),
+                    ColumnType::Integer | ColumnType::Float
+                )
+            });
+            any && all
+        })
+        .collect();
+
+    let mut out = String::new();
+    out.push_str(&border(&widths, '┌', '┬', '┐'));
+    out.push_str(&line(&header, &widths, &vec![false; columns]));
+    out.push_str(&border(&widths, '├', '┼', '┤'));
+    for row in &body {
+        out.push_str(&line(row, &widths, &numeric));
+    }
+    out.push_str(&border(&widths, '└', '┴', '┘'));
+    out
+}
+
+fn border(widths: &[usize], left: char, mid: char, right: char) -> String {
+    let parts: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+    format!("{}{}{}\n", left, parts.join(&mid.to_string()), right)
+}
+
+fn line(cells: &[String], widths: &[usize], right_align: &[bool]) -> String {
+    let mut out = String::from("│");
+    for ((cell, &width), &right) in cells.iter().zip(widths).zip(right_align) {
+        let text = truncate(cell, width);
+        let pad = " ".repeat(width - text.width());
+        if right {
+            out.push_str(&format!(" {}{} │", pad, text));
+        } else {
+            out.push_str(&format!(" {}{} │", text, pad));
+        }
+    }
+    out.push('\n');
+    out
+}
+
+// 换行和制表符会破坏表格布局
+fn clean(s: &str) -> String {
+    s.replace(['\r', '\n', '\t'], " ")
+}
+
+fn truncate(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or_default();
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_render_table_alignment() {
+        let headers = strings(&["Name", "Kit"]);
+        let rows = vec![strings(&["Perin", "37"]), strings(&["Szczesny", "1"])];
+        let table = render_table(&headers, &rows, None);
+        assert_eq!(
+            table,
+            "┌──────────┬─────┐\n\
+             │ Name     │ Kit │\n\
+             ├──────────┼─────┤\n\
+             │ Perin    │  37 │\n\
+             │ Szczesny │   1 │\n\
+             └──────────┴─────┘\n"
+        );
+    }
+
+    #[test]
+    fn test_render_table_truncates_to_width() {
+        let headers = strings(&["Name", "Nationality"]);
+        let rows = vec![strings(&["Wojciech Szczesny", "Poland"])];
+        let table = render_table(&headers, &rows, Some(20));
+        assert!(table.lines().all(|l| l.width() <= 20));
+        assert!(table.contains('…'));
+    }
+
+    #[test]
+    fn test_process_csv_show_paging() -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let table = process_csv_show("assets/juventus.csv", &opts, 1, Some(2), None, None)?;
+        // 三条边框 + 表头 + 两行数据
+        assert_eq!(table.lines().count(), 6);
+        assert!(table.contains("Mattia Perin"));
+        assert!(!table.contains("Wojciech Szczesny"));
+
+        let table = process_csv_show("assets/juventus.csv", &opts, 0, None, Some(1), None)?;
+        assert_eq!(table.lines().count(), 5);
+        Ok(())
+    }
+}
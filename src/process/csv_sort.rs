@@ -0,0 +1,208 @@
+use super::csv_reader::CsvReader;
+use anyhow::{anyhow, Result};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::vec;
+use tempfile::{NamedTempFile, TempPath};
+
+/// Default number of rows sorted in memory before a run is spilled to disk
+pub const DEFAULT_SORT_BUFFER: usize = 100_000;
+/// Most runs merged (and so files open) at once
+const MERGE_FAN_IN: usize = 64;
+
+// (key, run, fields), run 放在 key 后面保证相同 key 的行保持原来的顺序
+type Entry = (Vec<String>, usize, Vec<String>);
+
+/// Rows of a csv file in key order.
+///
+/// The input is read in chunks of `buffer_rows`, each chunk is sorted and
+/// spilled to a temp file, then the runs are merged back with a heap, so
+/// files larger than memory can be sorted. With more than 64 runs, groups of
+/// 64 are first merged into longer runs until few enough are left.
+pub struct SortedRecords {
+    runs: Vec<Run>,
+    heap: BinaryHeap<Reverse<Entry>>,
+    key: Vec<usize>,
+    // 溢出的 run 对应的临时文件, 读完之前不能删除
+    _files: Vec<TempPath>,
+}
+
+enum Run {
+    Memory(vec::IntoIter<Vec<String>>),
+    Spilled(csv::Reader<File>),
+}
+
+/// Resolve key column names to their positions in `headers`
+pub fn key_indices(headers: &StringRecord, key: &[String], input: &str) -> Result<Vec<usize>> {
+    key.iter()
+        .map(|name| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| anyhow!("Unknown key column {:?} in {}", name, input))
+        })
+        .collect()
+}
+
+impl SortedRecords {
+    pub fn new(mut reader: CsvReader, key: Vec<usize>, buffer_rows: usize) -> Result<Self> {
+        let buffer_rows = buffer_rows.max(1);
+        let mut files = Vec::new();
+        let mut chunk = Vec::new();
+        let mut record = StringRecord::new();
+        while reader.read_record(&mut record)? {
+            chunk.push(record.iter().map(String::from).collect::<Vec<_>>());
+            if chunk.len() >= buffer_rows {
+                chunk.sort_by_cached_key(|row| row_key(row, &key));
+                files.push(spill(chunk.drain(..).map(Ok))?);
+            }
+        }
+        // 最后一块不用写到磁盘, 直接留在内存里参与归并
+        chunk.sort_by_cached_key(|row| row_key(row, &key));
+
+        // 同时打开的文件数有上限: run 太多时每 64 个相邻的 run 先归并成一个,
+        // 相邻的 run 合并后先后顺序不变, 相同 key 的行仍然保持原来的顺序
+        while files.len() >= MERGE_FAN_IN {
+            let mut merged = Vec::new();
+            let mut rest = files.into_iter().peekable();
+            while rest.peek().is_some() {
+                let group: Vec<TempPath> = rest.by_ref().take(MERGE_FAN_IN).collect();
+                let runs = group.iter().map(open_run).collect::<Result<Vec<_>>>()?;
+                let rows = Self::merge(runs, key.clone(), group)?;
+                merged.push(spill(rows.map(|r| r.map(|(_, row)| row)))?);
+            }
+            files = merged;
+        }
+
+        let mut runs = files.iter().map(open_run).collect::<Result<Vec<_>>>()?;
+        runs.push(Run::Memory(chunk.into_iter()));
+        Self::merge(runs, key, files)
+    }
+
+    fn merge(runs: Vec<Run>, key: Vec<usize>, files: Vec<TempPath>) -> Result<Self> {
+        let mut sorted = Self {
+            runs,
+            heap: BinaryHeap::new(),
+            key,
+            _files: files,
+        };
+        for i in 0..sorted.runs.len() {
+            sorted.refill(i)?;
+        }
+        Ok(sorted)
+    }
+
+    fn refill(&mut self, run: usize) -> Result<()> {
+        let row = match &mut self.runs[run] {
+            Run::Memory(rows) => rows.next(),
+            Run::Spilled(reader) => {
+                let mut record = StringRecord::new();
+                if reader.read_record(&mut record)? {
+                    Some(record.iter().map(String::from).collect())
+                } else {
+                    None
+                }
+            }
+        };
+        if let Some(row) = row {
+            self.heap
+                .push(Reverse((row_key(&row, &self.key), run, row)));
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for SortedRecords {
+    /// (key, fields)
+    type Item = Result<(Vec<String>, Vec<String>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((key, run, row)) = self.heap.pop()?;
+        if let Err(e) = self.refill(run) {
+            return Some(Err(e));
+        }
+        Some(Ok((key, row)))
+    }
+}
+
+fn row_key(row: &[String], key: &[usize]) -> Vec<String> {
+    key.iter()
+        .map(|&i| row.get(i).cloned().unwrap_or_default())
+        .collect()
+}
+
+// 写完就关闭文件, 归并时再打开
+fn spill(rows: impl Iterator<Item = Result<Vec<String>>>) -> Result<TempPath> {
+    let mut writer = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(NamedTempFile::new()?);
+    for row in rows {
+        writer.write_record(row?)?;
+    }
+    let file = writer.into_inner().map_err(|e| anyhow!("{}", e))?;
+    Ok(file.into_temp_path())
+}
+
+fn open_run(path: &TempPath) -> Result<Run> {
+    let reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(File::open(path)?);
+    Ok(Run::Spilled(reader))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::csv_reader::open_csv;
+
+    #[test]
+    fn test_external_sort_matches_in_memory_sort() -> Result<()> {
+        let sorted = |buffer_rows| -> Result<Vec<String>> {
+            let (reader, headers) = open_csv("assets/juventus.csv", &Default::default())?;
+            let key = key_indices(&headers, &["Nationality".into(), "Name".into()], "")?;
+            SortedRecords::new(reader, key, buffer_rows)?
+                .map(|r| r.map(|(_, row)| row[0].clone()))
+                .collect()
+        };
+        let in_memory = sorted(DEFAULT_SORT_BUFFER)?;
+        assert_eq!(in_memory.len(), 27);
+        assert_eq!(in_memory[0], "Gonzalo Higuaín");
+        // 每 4 行一块, 需要归并 7 个 run
+        assert_eq!(sorted(4)?, in_memory);
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_sort_more_runs_than_fan_in() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("runs.csv");
+        let mut content = String::from("id,key\n");
+        for i in 0..300 {
+            content.push_str(&format!("{},{}\n", i, i * 37 % 11));
+        }
+        std::fs::write(&input, content)?;
+        let (reader, headers) = open_csv(&input.to_string_lossy(), &Default::default())?;
+        let key = key_indices(&headers, &["key".into()], "")?;
+        // 每 2 行一块, 150 个 run 超过上限, 先每 64 个归并成一个
+        let sorted = SortedRecords::new(reader, key, 2)?;
+        assert!(sorted.runs.len() <= MERGE_FAN_IN);
+        let ids: Vec<usize> = sorted
+            .map(|r| r.map(|(_, row)| row[0].parse().unwrap()))
+            .collect::<Result<_>>()?;
+        let mut expected: Vec<usize> = (0..300).collect();
+        expected.sort_by_key(|i| (i * 37 % 11).to_string());
+        assert_eq!(ids, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_key_column() -> Result<()> {
+        let (_, headers) = open_csv("assets/juventus.csv", &Default::default())?;
+        assert!(key_indices(&headers, &["Number".into()], "juventus.csv").is_err());
+        Ok(())
+    }
+}
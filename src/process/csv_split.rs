@@ -0,0 +1,448 @@
+use super::csv_reader::{open_csv, zip_record};
+use super::csv_sort::key_indices;
+use super::csv_writer::{csv_writer, row_writer};
+use crate::{CsvOutputOpts, CsvReaderOpts, OutputFile};
+use anyhow::Result;
+use csv::{StringRecord, Writer};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Files kept open at once by `--by-column`, the rest are reopened to append
+const MAX_OPEN_FILES: usize = 64;
+
+type FileWriter = Writer<Box<dyn Write>>;
+
+/// Split `input` into files of `rows` rows named `{stem}_0001.csv`, ... in
+/// `out_dir`, every file repeats the header. Each file is written through
+/// [`OutputFile`] and moved into place once it is full. Returns the files
+/// written.
+pub fn process_csv_split_rows(
+    input: &str,
+    rows: usize,
+    out_dir: &str,
+    no_clobber: bool,
+    opts: &CsvReaderOpts,
+) -> Result<Vec<PathBuf>> {
+    let (mut reader, headers) = open_csv(input, opts)?;
+    fs::create_dir_all(out_dir)?;
+    let stem = file_stem(input);
+    let rows = rows.max(1);
+    let mut files = Vec::new();
+    let mut current: Option<(OutputFile, FileWriter)> = None;
+    let mut count = 0;
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        if count % rows == 0 {
+            if let Some((file, writer)) = current.take() {
+                close(file, writer)?;
+            }
+            let path = Path::new(out_dir).join(format!("{}_{:04}.csv", stem, files.len() + 1));
+            current = Some(create(&path, &headers, no_clobber, opts)?);
+            files.push(path);
+        }
+        if let Some((_, w)) = current.as_mut() {
+            w.write_record(&record)?;
+        }
+        count += 1;
+    }
+    if let Some((file, writer)) = current {
+        close(file, writer)?;
+    }
+    Ok(files)
+}
+
+/// Split `input` into one file per distinct value of `column`, named
+/// `{stem}_{value}.csv` in `out_dir`, values that map to a name already in
+/// use get `-2`, `-3`, ... appended. The files are written through
+/// [`OutputFile`] and all moved into place at the end. Returns the files in
+/// first-seen order.
+pub fn process_csv_split_by(
+    input: &str,
+    column: &str,
+    out_dir: &str,
+    no_clobber: bool,
+    opts: &CsvReaderOpts,
+) -> Result<Vec<PathBuf>> {
+    let (mut reader, headers) = open_csv(input, opts)?;
+    let index = key_indices(&headers, &[column.to_string()], input)?[0];
+    fs::create_dir_all(out_dir)?;
+    let stem = file_stem(input);
+    let mut files = Vec::new();
+    // 不同的值可能清理成同一个文件名 ("a/b" 和 "a_b"), 记下每个值对应的文件
+    let mut paths: HashMap<String, PathBuf> = HashMap::new();
+    let mut taken = HashSet::new();
+    // 每个文件先写到临时文件, 中途关掉的也还没移到目标位置, 最后一起 commit
+    let mut outputs: HashMap<PathBuf, OutputFile> = HashMap::new();
+    let mut open: HashMap<PathBuf, FileWriter> = HashMap::new();
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        let value = record.get(index).unwrap_or_default();
+        let (path, new) = match paths.get(value) {
+            Some(path) => (path.clone(), false),
+            None => {
+                let path = unique_path(out_dir, &stem, value, &mut taken);
+                paths.insert(value.to_string(), path.clone());
+                (path, true)
+            }
+        };
+        if !open.contains_key(&path) {
+            // 值很多时不能同时打开所有文件, 全部关掉, 之后用追加模式重新打开
+            if open.len() >= MAX_OPEN_FILES {
+                for (_, mut w) in open.drain() {
+                    w.flush()?;
+                }
+            }
+            let writer = if new {
+                files.push(path.clone());
+                let (file, writer) = create(&path, &headers, no_clobber, opts)?;
+                outputs.insert(path.clone(), file);
+                writer
+            } else {
+                csv_writer(outputs[&path].reopen()?, opts)
+            };
+            open.insert(path.clone(), writer);
+        }
+        if let Some(w) = open.get_mut(&path) {
+            w.write_record(&record)?;
+        }
+    }
+    for (_, mut w) in open {
+        w.flush()?;
+    }
+    for path in &files {
+        if let Some(file) = outputs.remove(path) {
+            file.commit()?;
+        }
+    }
+    Ok(files)
+}
+
+// `{stem}_{value}.csv`, 文件名已经被别的值占用时加上 -2, -3, ...
+// 按小写比较, 不区分大小写的文件系统上 Italy 和 ITALY 也是同一个文件
+fn unique_path(out_dir: &str, stem: &str, value: &str, taken: &mut HashSet<String>) -> PathBuf {
+    let base = format!("{}_{}", stem, file_safe(value));
+    let mut name = format!("{}.csv", base);
+    let mut n = 1;
+    while !taken.insert(name.to_lowercase()) {
+        n += 1;
+        name = format!("{}-{}.csv", base, n);
+    }
+    Path::new(out_dir).join(name)
+}
+
+/// Pick `n` rows uniformly at random with reservoir sampling, only `n` rows
+/// are kept in memory. The sample keeps the order of the input.
+pub fn process_csv_sample(
+    input: &str,
+    n: usize,
+    seed: Option<u64>,
+    opts: &CsvReaderOpts,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let (mut reader, headers) = open_csv(input, opts)?;
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut reservoir: Vec<(usize, StringRecord)> = Vec::with_capacity(n);
+    let mut record = StringRecord::new();
+    let mut i = 0;
+    while reader.read_record(&mut record)? {
+        if reservoir.len() < n {
+            reservoir.push((i, record.clone()));
+        } else {
+            let j = rng.gen_range(0..=i);
+            if j < n {
+                reservoir[j] = (i, record.clone());
+            }
+        }
+        i += 1;
+    }
+    reservoir.sort_by_key(|(i, _)| *i);
+    write_records(&headers, reservoir.into_iter().map(|(_, r)| r), opts, out)
+}
+
+/// The first `n` rows, the rest of the input is not read
+pub fn process_csv_head(
+    input: &str,
+    n: usize,
+    opts: &CsvReaderOpts,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let (mut reader, headers) = open_csv(input, opts)?;
+    let records = reader.records().take(n).collect::<Result<Vec<_>, _>>()?;
+    write_records(&headers, records, opts, out)
+}
+
+/// The last `n` rows, only `n` rows are kept in memory
+pub fn process_csv_tail(
+    input: &str,
+    n: usize,
+    opts: &CsvReaderOpts,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let (mut reader, headers) = open_csv(input, opts)?;
+    let mut last = VecDeque::with_capacity(n);
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        if n == 0 {
+            continue;
+        }
+        if last.len() == n {
+            last.pop_front();
+        }
+        last.push_back(record.clone());
+    }
+    write_records(&headers, last, opts, out)
+}
+
+fn write_records(
+    headers: &StringRecord,
+    records: impl IntoIterator<Item = StringRecord>,
+    opts: &CsvReaderOpts,
+    out: &CsvOutputOpts,
+) -> Result<()> {
+    let names: Vec<String> = headers.iter().map(String::from).collect();
+    let (file, output) = out.create()?;
+    let mut writer = row_writer(output, out.format(), &names, opts)?;
+    for record in records {
+        let row: Map<String, Value> = zip_record(headers, record.iter())
+            .map(|(name, v)| (name, Value::String(v.to_string())))
+            .collect();
+        writer.write_row(&Value::Object(row))?;
+    }
+    writer.finish()?;
+    file.commit()
+}
+
+fn create(
+    path: &Path,
+    headers: &StringRecord,
+    no_clobber: bool,
+    opts: &CsvReaderOpts,
+) -> Result<(OutputFile, FileWriter)> {
+    let (file, output) = OutputFile::create(Some(&path.to_string_lossy()), no_clobber)?;
+    let mut writer = csv_writer(output, opts);
+    if opts.header {
+        writer.write_record(headers)?;
+    }
+    Ok((file, writer))
+}
+
+fn close(file: OutputFile, mut writer: FileWriter) -> Result<()> {
+    writer.flush()?;
+    drop(writer);
+    file.commit()
+}
+
+fn file_stem(input: &str) -> String {
+    if input == "-" {
+        return "stdin".to_string();
+    }
+    Path::new(input)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "split".to_string())
+}
+
+// 列的值用作文件名, 去掉路径分隔符之类的字符
+fn file_safe(value: &str) -> String {
+    if value.trim().is_empty() {
+        return "empty".to_string();
+    }
+    value
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(path: &Path) -> Result<Vec<String>> {
+        Ok(fs::read_to_string(path)?
+            .lines()
+            .map(String::from)
+            .collect())
+    }
+
+    #[test]
+    fn test_split_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out_dir = dir.path().to_string_lossy();
+        let files = process_csv_split_rows(
+            "assets/juventus.csv",
+            10,
+            &out_dir,
+            false,
+            &Default::default(),
+        )?;
+        assert_eq!(files.len(), 3);
+        assert!(files[0].ends_with("juventus_0001.csv"));
+        let last = lines(&files[2])?;
+        assert_eq!(last.len(), 8);
+        assert_eq!(last[0], "Name,Position,DOB,Nationality,Kit Number");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_column() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out_dir = dir.path().to_string_lossy();
+        let files = process_csv_split_by(
+            "assets/juventus.csv",
+            "Nationality",
+            &out_dir,
+            false,
+            &Default::default(),
+        )?;
+        let italy = files
+            .iter()
+            .find(|f| f.ends_with("juventus_Italy.csv"))
+            .unwrap();
+        let rows = lines(italy)?;
+        assert_eq!(rows[0], "Name,Position,DOB,Nationality,Kit Number");
+        assert!(rows[1..].iter().all(|r| r.contains(",Italy,")));
+        let total: usize = files
+            .iter()
+            .map(|f| lines(f).map(|l| l.len() - 1))
+            .sum::<Result<_>>()?;
+        assert_eq!(total, 27);
+        assert_eq!(file_safe("Bosnia/Herzegovina"), "Bosnia_Herzegovina");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_clashing_values() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("teams.csv");
+        fs::write(
+            &input,
+            "Name,Team\nPerin,a/b\nBuffon,a_b\nSzczesny,a/b\nChiellini,A_B\n",
+        )?;
+        let out_dir = dir.path().join("out");
+        let files = process_csv_split_by(
+            &input.to_string_lossy(),
+            "Team",
+            &out_dir.to_string_lossy(),
+            false,
+            &Default::default(),
+        )?;
+        let names: Vec<_> = files.iter().map(|f| f.file_name().unwrap()).collect();
+        assert_eq!(
+            names,
+            ["teams_a_b.csv", "teams_a_b-2.csv", "teams_A_B-3.csv"]
+        );
+        assert_eq!(
+            lines(&files[0])?,
+            ["Name,Team", "Perin,a/b", "Szczesny,a/b"]
+        );
+        assert_eq!(lines(&files[1])?, ["Name,Team", "Buffon,a_b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_many_values() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("kits.csv");
+        // 比 MAX_OPEN_FILES 多的值, 第一个值的文件会被关掉再重新打开
+        let mut content = String::from("Kit\n");
+        for i in 0..MAX_OPEN_FILES + 6 {
+            content.push_str(&format!("{}\n", i));
+        }
+        content.push_str("0\n");
+        fs::write(&input, content)?;
+        let out_dir = dir.path().join("out");
+        let files = process_csv_split_by(
+            &input.to_string_lossy(),
+            "Kit",
+            &out_dir.to_string_lossy(),
+            false,
+            &Default::default(),
+        )?;
+        assert_eq!(files.len(), MAX_OPEN_FILES + 6);
+        assert_eq!(lines(&files[0])?, ["Kit", "0", "0"]);
+        // 临时文件都已经移到目标位置
+        assert_eq!(fs::read_dir(&out_dir)?.count(), files.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_no_clobber() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out_dir = dir.path().to_string_lossy();
+        let existing = dir.path().join("juventus_0002.csv");
+        fs::write(&existing, "keep\n")?;
+        let ret = process_csv_split_rows(
+            "assets/juventus.csv",
+            10,
+            &out_dir,
+            true,
+            &Default::default(),
+        );
+        assert!(ret.unwrap_err().to_string().contains("--no-clobber"));
+        assert_eq!(fs::read_to_string(&existing)?, "keep\n");
+
+        let existing = dir.path().join("juventus_Italy.csv");
+        fs::write(&existing, "keep\n")?;
+        let ret = process_csv_split_by(
+            "assets/juventus.csv",
+            "Nationality",
+            &out_dir,
+            true,
+            &Default::default(),
+        );
+        assert!(ret.unwrap_err().to_string().contains("--no-clobber"));
+        assert_eq!(fs::read_to_string(&existing)?, "keep\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_head_tail() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out = CsvOutputOpts {
+            output: Some(dir.path().join("out.csv").to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let path = PathBuf::from(out.output.clone().unwrap());
+        let opts = CsvReaderOpts::default();
+
+        process_csv_sample("assets/juventus.csv", 5, Some(42), &opts, &out)?;
+        let first = lines(&path)?;
+        assert_eq!(first.len(), 6);
+        process_csv_sample("assets/juventus.csv", 5, Some(42), &opts, &out)?;
+        assert_eq!(lines(&path)?, first);
+        process_csv_sample("assets/juventus.csv", 100, None, &opts, &out)?;
+        assert_eq!(lines(&path)?.len(), 28);
+
+        process_csv_head("assets/juventus.csv", 2, &opts, &out)?;
+        let head = lines(&path)?;
+        assert_eq!(head.len(), 3);
+        assert!(head[1].starts_with("Wojciech Szczesny,"));
+
+        process_csv_tail("assets/juventus.csv", 2, &opts, &out)?;
+        let tail = lines(&path)?;
+        assert_eq!(tail.len(), 3);
+        assert_eq!(
+            tail[2],
+            fs::read_to_string("assets/juventus.csv")?
+                .lines()
+                .last()
+                .unwrap()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,343 @@
+use super::csv_reader::open_csv;
+use super::csv_show::render_table;
+use crate::{ColumnType, CsvReaderOpts};
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use hyperloglogplus::{HyperLogLog, HyperLogLogPlus};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+/// Profile of one column
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: ColumnType,
+    pub non_null: u64,
+    pub nulls: u64,
+    pub distinct: u64,
+    /// `distinct` is a HyperLogLog estimate
+    pub approximate: bool,
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    /// Most frequent values, most frequent first
+    pub top: Vec<ValueCount>,
+    /// Longest value in characters
+    pub max_length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: u64,
+}
+
+type Hll = HyperLogLogPlus<str, BuildHasherDefault<DefaultHasher>>;
+
+// 精确模式下记录每个值出现的次数; 近似模式用 HyperLogLog 估算不同值的个数,
+// 用 Space-Saving 算法在固定大小的表里追踪高频值
+enum Frequencies {
+    Exact(HashMap<String, u64>),
+    Approx {
+        hll: Box<Hll>,
+        heavy: HashMap<String, u64>,
+        capacity: usize,
+    },
+}
+
+struct ColumnProfile {
+    ty: ColumnType,
+    non_null: u64,
+    nulls: u64,
+    frequencies: Frequencies,
+    numbers: Vec<f64>,
+    sum: f64,
+    min: Option<String>,
+    max: Option<String>,
+    max_length: usize,
+}
+
+/// Profile every column of a csv file in a single pass.
+///
+/// With `approximate` the distinct count and top values use fixed size
+/// sketches, only the numbers kept for the median grow with the input.
+pub fn process_csv_stats(
+    input: &str,
+    opts: &CsvReaderOpts,
+    top: usize,
+    approximate: bool,
+) -> Result<Vec<ColumnStats>> {
+    let (mut reader, headers) = open_csv(input, opts)?;
+    let mut profiles = Vec::with_capacity(headers.len());
+    for _ in 0..headers.len() {
+        profiles.push(ColumnProfile::new(approximate, top)?);
+    }
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        for (i, profile) in profiles.iter_mut().enumerate() {
+            profile.add(record.get(i).unwrap_or_default());
+        }
+    }
+    Ok(headers
+        .iter()
+        .zip(profiles)
+        .map(|(name, profile)| profile.finish(name, top))
+        .collect())
+}
+
+/// Render column stats as a table, one row per column
+pub fn render_stats(stats: &[ColumnStats], max_width: Option<usize>) -> String {
+    let headers: Vec<String> = [
+        "column", "type", "non-null", "null", "distinct", "min", "max", "mean", "median",
+        "max len", "top",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    let rows: Vec<Vec<String>> = stats
+        .iter()
+        .map(|s| {
+            let distinct = if s.approximate {
+                format!("~{}", s.distinct)
+            } else {
+                s.distinct.to_string()
+            };
+            let top: Vec<String> = s
+                .top
+                .iter()
+                .map(|v| format!("{} ({})", v.value, v.count))
+                .collect();
+            vec![
+                s.name.clone(),
+                s.ty.to_string(),
+                s.non_null.to_string(),
+                s.nulls.to_string(),
+                distinct,
+                s.min.as_ref().map(value_to_string).unwrap_or_default(),
+                s.max.as_ref().map(value_to_string).unwrap_or_default(),
+                s.mean.map(format_float).unwrap_or_default(),
+                s.median.map(format_float).unwrap_or_default(),
+                s.max_length.to_string(),
+                top.join(", "),
+            ]
+        })
+        .collect();
+    render_table(&headers, &rows, max_width)
+}
+
+impl ColumnProfile {
+    fn new(approximate: bool, top: usize) -> Result<Self> {
+        let frequencies = if approximate {
+            let hll = Hll::new(14, Default::default())
+                .map_err(|e| anyhow!("cannot create HyperLogLog: {:?}", e))?;
+            Frequencies::Approx {
+                hll: Box::new(hll),
+                heavy: HashMap::new(),
+                capacity: (top * 10).max(100),
+            }
+        } else {
+            Frequencies::Exact(HashMap::new())
+        };
+        Ok(Self {
+            ty: ColumnType::Null,
+            non_null: 0,
+            nulls: 0,
+            frequencies,
+            numbers: Vec::new(),
+            sum: 0.0,
+            min: None,
+            max: None,
+            max_length: 0,
+        })
+    }
+
+    fn add(&mut self, cell: &str) {
+        let ty = ColumnType::infer(cell, true);
+        if ty == ColumnType::Null {
+            self.nulls += 1;
+            return;
+        }
+        self.non_null += 1;
+        self.ty = self.ty.merge(ty);
+        self.max_length = self.max_length.max(cell.chars().count());
+        if matches!(ty, ColumnType::Integer | ColumnType::Float) {
+            if let Ok(n) = cell.trim().parse::<f64>() {
+                self.numbers.push(n);
+                self.sum += n;
+            }
+        }
+        if self.min.as_deref().is_none_or(|min| cell < min) {
+            self.min = Some(cell.to_string());
+        }
+        if self.max.as_deref().is_none_or(|max| cell > max) {
+            self.max = Some(cell.to_string());
+        }
+        self.frequencies.add(cell);
+    }
+
+    fn finish(mut self, name: &str, top: usize) -> ColumnStats {
+        let numeric = matches!(self.ty, ColumnType::Integer | ColumnType::Float);
+        let (min, max, mean, median) = if numeric && !self.numbers.is_empty() {
+            self.numbers.sort_by(f64::total_cmp);
+            let n = self.numbers.len();
+            let median = if n % 2 == 1 {
+                self.numbers[n / 2]
+            } else {
+                (self.numbers[n / 2 - 1] + self.numbers[n / 2]) / 2.0
+            };
+            let number = |x: f64| match self.ty {
+                ColumnType::Integer => Value::from(x as i64),
+                _ => Value::from(x),
+            };
+            (
+                Some(number(self.numbers[0])),
+                Some(number(self.numbers[n - 1])),
+                Some(self.sum / n as f64),
+                Some(median),
+            )
+        } else {
+            (
+                self.min.map(Value::String),
+                self.max.map(Value::String),
+                None,
+                None,
+            )
+        };
+        let (distinct, approximate, top) = self.frequencies.finish(top);
+        ColumnStats {
+            name: name.to_string(),
+            ty: self.ty,
+            non_null: self.non_null,
+            nulls: self.nulls,
+            distinct,
+            approximate,
+            min,
+            max,
+            mean,
+            median,
+            top,
+            max_length: self.max_length,
+        }
+    }
+}
+
+impl Frequencies {
+    fn add(&mut self, cell: &str) {
+        match self {
+            Frequencies::Exact(counts) => {
+                if let Some(count) = counts.get_mut(cell) {
+                    *count += 1;
+                } else {
+                    counts.insert(cell.to_string(), 1);
+                }
+            }
+            Frequencies::Approx {
+                hll,
+                heavy,
+                capacity,
+            } => {
+                hll.insert(cell);
+                if let Some(count) = heavy.get_mut(cell) {
+                    *count += 1;
+                } else if heavy.len() < *capacity {
+                    heavy.insert(cell.to_string(), 1);
+                } else {
+                    // 表满了就替换掉计数最小的值, 新值继承它的计数
+                    let (victim, min) = heavy
+                        .iter()
+                        .min_by_key(|(_, c)| **c)
+                        .map(|(k, c)| (k.clone(), *c))
+                        .unwrap_or_default();
+                    heavy.remove(&victim);
+                    heavy.insert(cell.to_string(), min + 1);
+                }
+            }
+        }
+    }
+
+    fn finish(self, top: usize) -> (u64, bool, Vec<ValueCount>) {
+        let (distinct, approximate, counts) = match self {
+            Frequencies::Exact(counts) => (counts.len() as u64, false, counts),
+            Frequencies::Approx { mut hll, heavy, .. } => (hll.count().round() as u64, true, heavy),
+        };
+        let mut counts: Vec<ValueCount> = counts
+            .into_iter()
+            .map(|(value, count)| ValueCount { value, count })
+            .collect();
+        // 次数相同时按值排序, 保证输出稳定
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        counts.truncate(top);
+        (distinct, approximate, counts)
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn format_float(f: f64) -> String {
+    let s = format!("{:.4}", f);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_stats() -> Result<()> {
+        let stats = process_csv_stats("assets/juventus.csv", &Default::default(), 2, false)?;
+        assert_eq!(stats.len(), 5);
+
+        let position = &stats[1];
+        assert_eq!(position.ty, ColumnType::String);
+        assert_eq!(position.non_null, 27);
+        assert_eq!(position.top[0].value, "Central Midfield");
+        assert_eq!(position.top[0].count, 6);
+
+        let kit = &stats[4];
+        assert_eq!(kit.ty, ColumnType::Integer);
+        assert_eq!(kit.distinct, 27);
+        assert_eq!(kit.min, Some(Value::from(1)));
+        assert_eq!(kit.max, Some(Value::from(77)));
+        assert_eq!(kit.max_length, 2);
+        assert!(kit.mean.is_some() && kit.median.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_stats_approximate() -> Result<()> {
+        let exact = process_csv_stats("assets/juventus.csv", &Default::default(), 3, false)?;
+        let approx = process_csv_stats("assets/juventus.csv", &Default::default(), 3, true)?;
+        for (e, a) in exact.iter().zip(&approx) {
+            assert!(a.approximate);
+            assert_eq!(e.distinct, a.distinct);
+            assert_eq!(e.top, a.top);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_stats() -> Result<()> {
+        let stats = process_csv_stats("assets/juventus.csv", &Default::default(), 1, false)?;
+        let table = render_stats(&stats, None);
+        assert!(table.contains("Kit Number"));
+        assert!(table.contains("Italy ("));
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(2.5), "2.5");
+        assert_eq!(format_float(3.0), "3");
+        assert_eq!(format_float(1.0 / 3.0), "0.3333");
+    }
+}
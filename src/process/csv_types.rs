@@ -0,0 +1,325 @@
+use super::csv_reader::{zip_record, CsvReader};
+use crate::{ColumnType, CsvTypeOpts};
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use serde_json::{Map, Number, Value};
+use std::vec;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime};
+
+impl ColumnType {
+    /// Guess the type of a single cell
+    pub fn infer(cell: &str, infer_dates: bool) -> Self {
+        let cell = cell.trim();
+        if cell.is_empty() {
+            ColumnType::Null
+        } else if is_bool(cell) {
+            ColumnType::Boolean
+        } else if is_integer(cell) {
+            ColumnType::Integer
+        } else if is_float(cell) {
+            ColumnType::Float
+        } else if infer_dates && is_date(cell) {
+            ColumnType::Date
+        } else {
+            ColumnType::String
+        }
+    }
+
+    /// The narrowest type that can hold values of both types
+    pub fn merge(self, other: Self) -> Self {
+        use ColumnType::*;
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Null, t) | (t, Null) => t,
+            (Integer, Float) | (Float, Integer) => Float,
+            _ => String,
+        }
+    }
+
+    /// Convert a cell to a json value of this type, empty cells become null
+    pub fn convert(self, cell: &str) -> Result<Value> {
+        let trimmed = cell.trim();
+        if trimmed.is_empty() && self != ColumnType::String {
+            return Ok(Value::Null);
+        }
+        let value = match self {
+            ColumnType::Null => return Err(anyhow!("expected an empty cell")),
+            ColumnType::Boolean => Value::Bool(trimmed.to_lowercase().parse()?),
+            ColumnType::Integer => Value::Number(trimmed.parse::<i64>()?.into()),
+            ColumnType::Float => {
+                let f = trimmed.parse::<f64>()?;
+                Value::Number(Number::from_f64(f).ok_or_else(|| anyhow!("not a finite number"))?)
+            }
+            ColumnType::Date if is_date(trimmed) => Value::String(trimmed.to_string()),
+            ColumnType::Date => return Err(anyhow!("not an ISO 8601 date")),
+            ColumnType::String => Value::String(cell.to_string()),
+        };
+        Ok(value)
+    }
+}
+
+/// Resolve the type of every column: explicit overrides first, then inference
+/// over `records`, falling back to string when inference is disabled.
+pub fn resolve_column_types(
+    headers: &StringRecord,
+    records: &[StringRecord],
+    opts: &CsvTypeOpts,
+) -> Result<Vec<ColumnType>> {
+    let mut types = vec![ColumnType::Null; headers.len()];
+    if opts.no_infer {
+        types.fill(ColumnType::String);
+    } else {
+        for record in records {
+            for (i, cell) in record.iter().enumerate().take(types.len()) {
+                types[i] = types[i].merge(ColumnType::infer(cell, opts.infer_dates));
+            }
+        }
+    }
+    for (name, ty) in &opts.types {
+        let Some(i) = headers.iter().position(|h| h == name) else {
+            return Err(anyhow!("Unknown column {:?} in --types", name));
+        };
+        types[i] = *ty;
+    }
+    Ok(types)
+}
+
+/// Which columns had their type set explicitly with `--types`
+pub fn pinned_columns(headers: &StringRecord, opts: &CsvTypeOpts) -> Vec<bool> {
+    headers
+        .iter()
+        .map(|h| opts.types.iter().any(|(name, _)| name == h))
+        .collect()
+}
+
+/// Convert a record to typed json values, fields beyond `types` stay strings.
+///
+/// A cell that doesn't fit an inferred type widens that column for the rest
+/// of the file (down to string); only columns in `pinned` fail on a mismatch.
+pub fn convert_record(
+    headers: &StringRecord,
+    record: &StringRecord,
+    types: &mut [ColumnType],
+    pinned: &[bool],
+) -> Result<Vec<Value>> {
+    let mut values = Vec::with_capacity(record.len());
+    for (i, cell) in record.iter().enumerate() {
+        let Some(ty) = types.get_mut(i) else {
+            values.push(Value::String(cell.to_string()));
+            continue;
+        };
+        let value = match ty.convert(cell) {
+            Ok(value) => value,
+            Err(e) if pinned.get(i).copied().unwrap_or_default() => {
+                return Err(anyhow!(
+                    "column {:?}: cannot convert {:?} to {}: {}",
+                    headers.get(i).unwrap_or_default(),
+                    cell,
+                    ty,
+                    e
+                ));
+            }
+            Err(_) => {
+                // 推断窗口之外出现了放不下的值, 放宽这一列的类型, 最差退化为字符串
+                *ty = ty.merge(ColumnType::infer(cell, true));
+                ty.convert(cell)
+                    .unwrap_or_else(|_| Value::String(cell.to_string()))
+            }
+        };
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Rows of a csv file as header -> typed value maps.
+///
+/// Column types are inferred from the first `--infer-rows` rows, which are
+/// buffered and then yielded before the rest of the file is read. Later
+/// cells that don't fit widen their column instead of failing.
+pub struct TypedRows {
+    reader: CsvReader,
+    headers: StringRecord,
+    types: Vec<ColumnType>,
+    pinned: Vec<bool>,
+    sample: vec::IntoIter<StringRecord>,
+    record: StringRecord,
+    rows: u64,
+}
+
+impl TypedRows {
+    pub fn new(mut reader: CsvReader, headers: StringRecord, opts: &CsvTypeOpts) -> Result<Self> {
+        let mut sample = Vec::new();
+        let mut record = StringRecord::new();
+        while sample.len() < opts.infer_rows && reader.read_record(&mut record)? {
+            sample.push(record.clone());
+        }
+        let types = resolve_column_types(&headers, &sample, opts)?;
+        let pinned = pinned_columns(&headers, opts);
+        Ok(Self {
+            reader,
+            headers,
+            types,
+            pinned,
+            sample: sample.into_iter(),
+            record,
+            rows: 0,
+        })
+    }
+
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    /// Rows yielded so far
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Bytes of input read so far
+    pub fn bytes(&self) -> u64 {
+        self.reader.position().byte()
+    }
+
+    fn next_row(&mut self) -> Result<Option<Map<String, Value>>> {
+        let record = match self.sample.next() {
+            Some(record) => record,
+            None if self.reader.read_record(&mut self.record)? => self.record.clone(),
+            None => return Ok(None),
+        };
+        self.rows += 1;
+        let values = convert_record(&self.headers, &record, &mut self.types, &self.pinned)
+            .map_err(|e| anyhow!("row {}: {} (type set with --types)", self.rows, e))?;
+        // zip_record() 将 headers 和 values 合并为一个元组的迭代器 [(header, value), ...]
+        // collect::<Map>() 将元组迭代器转换为 Json 对象
+        // 因为 Json Map 也实现了 std::iter::FromIterator trait
+        Ok(Some(zip_record(&self.headers, values).collect()))
+    }
+}
+
+impl Iterator for TypedRows {
+    type Item = Result<Map<String, Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_row().transpose()
+    }
+}
+
+fn is_bool(s: &str) -> bool {
+    s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("false")
+}
+
+// 以 0 开头的数字(如邮编 "01234")保留为字符串, 避免丢失信息
+fn has_leading_zero(s: &str) -> bool {
+    let digits = s.trim_start_matches(['+', '-']);
+    digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.")
+}
+
+fn is_integer(s: &str) -> bool {
+    s.parse::<i64>().is_ok() && !has_leading_zero(s)
+}
+
+fn is_float(s: &str) -> bool {
+    // f64 也接受 "inf", "NaN" 之类的写法, 这里只认普通的数字
+    s.bytes().any(|b| b.is_ascii_digit())
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'+' | b'-' | b'.' | b'e' | b'E'))
+        && s.parse::<f64>().is_ok()
+        && !has_leading_zero(s)
+}
+
+fn is_date(s: &str) -> bool {
+    Date::parse(s, format_description!("[year]-[month]-[day]")).is_ok()
+        || OffsetDateTime::parse(s, &Rfc3339).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_cell() {
+        assert_eq!(ColumnType::infer("", false), ColumnType::Null);
+        assert_eq!(ColumnType::infer("True", false), ColumnType::Boolean);
+        assert_eq!(ColumnType::infer("37", false), ColumnType::Integer);
+        assert_eq!(ColumnType::infer("-1.5e3", false), ColumnType::Float);
+        assert_eq!(ColumnType::infer("NaN", false), ColumnType::String);
+        assert_eq!(ColumnType::infer("01234", false), ColumnType::String);
+        assert_eq!(ColumnType::infer("1990-04-18", false), ColumnType::String);
+        assert_eq!(ColumnType::infer("1990-04-18", true), ColumnType::Date);
+        assert_eq!(
+            ColumnType::infer("2019-06-01T10:00:00Z", true),
+            ColumnType::Date
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        use ColumnType::*;
+        assert_eq!(Null.merge(Integer), Integer);
+        assert_eq!(Integer.merge(Float), Float);
+        assert_eq!(Boolean.merge(Integer), String);
+        assert_eq!(Date.merge(Null), Date);
+    }
+
+    #[test]
+    fn test_resolve_and_convert() -> Result<()> {
+        let headers = StringRecord::from(vec!["Name", "Kit Number", "Score"]);
+        let records = vec![
+            StringRecord::from(vec!["Perin", "37", "1"]),
+            StringRecord::from(vec!["Buffon", "", "2.5"]),
+        ];
+        let opts = CsvTypeOpts::default();
+        let mut types = resolve_column_types(&headers, &records, &opts)?;
+        assert_eq!(
+            types,
+            [ColumnType::String, ColumnType::Integer, ColumnType::Float]
+        );
+        let values = convert_record(&headers, &records[1], &mut types, &[false; 3])?;
+        assert_eq!(
+            values,
+            [Value::from("Buffon"), Value::Null, Value::from(2.5)]
+        );
+
+        let opts = CsvTypeOpts {
+            types: vec![("Kit Number".into(), ColumnType::String)],
+            ..Default::default()
+        };
+        let types = resolve_column_types(&headers, &records, &opts)?;
+        assert_eq!(types[1], ColumnType::String);
+
+        let opts = CsvTypeOpts {
+            types: vec![("Number".into(), ColumnType::String)],
+            ..Default::default()
+        };
+        assert!(resolve_column_types(&headers, &records, &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_error() {
+        let headers = StringRecord::from(vec!["Kit Number"]);
+        let record = StringRecord::from(vec!["ten"]);
+        let mut types = [ColumnType::Integer];
+        let err = convert_record(&headers, &record, &mut types, &[true]).unwrap_err();
+        assert!(err.to_string().contains("Kit Number"));
+    }
+
+    #[test]
+    fn test_convert_widens_inferred() -> Result<()> {
+        let headers = StringRecord::from(vec!["Kit Number", "Score", "Note"]);
+        let mut types = [ColumnType::Integer, ColumnType::Integer, ColumnType::Null];
+        let record = StringRecord::from(vec!["ten", "2.5", "captain"]);
+        let values = convert_record(&headers, &record, &mut types, &[false; 3])?;
+        assert_eq!(
+            values,
+            [Value::from("ten"), Value::from(2.5), Value::from("captain")]
+        );
+        assert_eq!(
+            types,
+            [ColumnType::String, ColumnType::Float, ColumnType::String]
+        );
+        Ok(())
+    }
+}
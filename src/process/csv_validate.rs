@@ -0,0 +1,335 @@
+use super::csv_encoding::DecodeError;
+use super::csv_fake::FakeKind;
+use super::csv_reader::open_csv;
+use crate::{ColumnType, CsvReaderOpts};
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+/// Declared shape of a csv file, loaded from yaml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CsvSchema {
+    pub columns: Vec<ColumnSchema>,
+    /// Columns not declared in the schema are errors
+    #[serde(default)]
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnSchema {
+    pub name: String,
+    #[serde(rename = "type", default = "default_type")]
+    pub ty: ColumnType,
+    /// The column must exist and cells must not be empty
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub unique: bool,
+    /// Regex every non-empty cell must match
+    pub pattern: Option<String>,
+    /// Allowed values
+    #[serde(rename = "enum")]
+    pub allowed: Option<Vec<String>>,
+    /// Inclusive bounds for int/float columns
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// What `rcli csv fake` generates, by default it follows enum/pattern/type
+    pub fake: Option<FakeKind>,
+}
+
+/// One failed check, `line` is the physical line in the csv file and
+/// `column` is 1-based
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationError {
+    pub line: u64,
+    pub column: usize,
+    pub name: String,
+    pub message: String,
+}
+
+fn default_type() -> ColumnType {
+    ColumnType::String
+}
+
+impl CsvSchema {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let schema: Self = serde_yaml::from_str(&content)?;
+        Ok(schema)
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+// 每一列编译好的检查规则, 以及 unique 检查用到的已出现的值
+struct ColumnCheck<'a> {
+    schema: &'a ColumnSchema,
+    index: usize,
+    pattern: Option<Regex>,
+    seen: HashSet<String>,
+}
+
+pub fn process_csv_validate(
+    input: &str,
+    schema: &CsvSchema,
+    opts: &CsvReaderOpts,
+) -> Result<Vec<ValidationError>> {
+    let (mut reader, headers) = open_csv(input, opts)?;
+    let mut errors = Vec::new();
+    // 表头记录的位置是在跳过注释行之前记下的, 这里用读完表头后的位置往回推一行
+    let header_line = match opts.header {
+        true => {
+            let start = headers.position().map(|p| p.line()).unwrap_or(1);
+            (reader.position().line() - 1).max(start)
+        }
+        false => 0,
+    };
+    let mut checks = Vec::new();
+    for column in &schema.columns {
+        let Some(index) = headers.iter().position(|h| h == column.name) else {
+            if column.required {
+                errors.push(ValidationError {
+                    line: header_line,
+                    column: 0,
+                    name: column.name.clone(),
+                    message: format!("missing required column {:?}", column.name),
+                });
+            }
+            continue;
+        };
+        let pattern = match &column.pattern {
+            Some(p) => Some(
+                Regex::new(p)
+                    .map_err(|e| anyhow!("invalid pattern for column {:?}: {}", column.name, e))?,
+            ),
+            None => None,
+        };
+        checks.push(ColumnCheck {
+            schema: column,
+            index,
+            pattern,
+            seen: HashSet::new(),
+        });
+    }
+    if schema.strict {
+        for (i, name) in headers.iter().enumerate() {
+            if !schema.columns.iter().any(|c| c.name == name) {
+                errors.push(ValidationError {
+                    line: header_line,
+                    column: i + 1,
+                    name: name.to_string(),
+                    message: format!("unexpected column {:?}", name),
+                });
+            }
+        }
+    }
+
+    let mut record = StringRecord::new();
+    loop {
+        match reader.read_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            // 字段数不对或编码错误的行记一条错误, 接着检查后面的行
+            Err(e) => {
+                errors.push(malformed_row(e)?);
+                continue;
+            }
+        }
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        for check in checks.iter_mut() {
+            let cell = record.get(check.index).unwrap_or_default();
+            if let Some(message) = check.validate(cell) {
+                errors.push(ValidationError {
+                    line,
+                    column: check.index + 1,
+                    name: check.schema.name.clone(),
+                    message: format!("{}: {}", check.schema.name, message),
+                });
+            }
+        }
+    }
+    Ok(errors)
+}
+
+// 行本身格式有问题的错误记下来继续; 编码错误之后 csv reader 不能再读,
+// 记下来结束检查; 其它 io 错误仍然中止
+fn malformed_row(e: csv::Error) -> Result<ValidationError> {
+    let (line, message) = match e.kind() {
+        csv::ErrorKind::UnequalLengths {
+            pos,
+            expected_len,
+            len,
+        } => (
+            pos.as_ref().map(|p| p.line()).unwrap_or_default(),
+            format!("expected {} fields, found {}", expected_len, len),
+        ),
+        csv::ErrorKind::Io(err) => {
+            match err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()) {
+                Some(decode) => (
+                    decode.line,
+                    format!(
+                        "invalid {} byte sequence, the rest of the file is not checked",
+                        decode.encoding.name()
+                    ),
+                ),
+                None => return Err(e.into()),
+            }
+        }
+        _ => return Err(e.into()),
+    };
+    Ok(ValidationError {
+        line,
+        column: 0,
+        name: String::new(),
+        message,
+    })
+}
+
+impl ColumnCheck<'_> {
+    fn validate(&mut self, cell: &str) -> Option<String> {
+        let schema = self.schema;
+        if cell.trim().is_empty() {
+            return schema
+                .required
+                .then(|| "required value is empty".to_string());
+        }
+        let value = match schema.ty.convert(cell) {
+            Ok(v) => v,
+            Err(_) => return Some(format!("{:?} is not a valid {}", cell, schema.ty)),
+        };
+        if let Value::Number(n) = &value {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.min.filter(|min| n < *min) {
+                return Some(format!("{} is less than {}", cell, min));
+            }
+            if let Some(max) = schema.max.filter(|max| n > *max) {
+                return Some(format!("{} is greater than {}", cell, max));
+            }
+        }
+        if let Some(allowed) = &schema.allowed {
+            if !allowed.iter().any(|a| a == cell) {
+                return Some(format!("{:?} is not one of {}", cell, allowed.join(", ")));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(cell) {
+                return Some(format!("{:?} does not match {}", cell, pattern));
+            }
+        }
+        if schema.unique && !self.seen.insert(cell.to_string()) {
+            return Some(format!("duplicate value {:?}", cell));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_juventus() -> Result<()> {
+        let schema = CsvSchema::load("fixtures/juventus.schema.yaml")?;
+        let errors = process_csv_validate("assets/juventus.csv", &schema, &Default::default())?;
+        assert!(errors.is_empty(), "{:?}", errors);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_reports_line_and_column() -> Result<()> {
+        let schema = CsvSchema::load("fixtures/juventus.schema.yaml")?;
+        let errors = process_csv_validate(
+            "fixtures/juventus_invalid.csv",
+            &schema,
+            &Default::default(),
+        )?;
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            [
+                "3:2: Position: \"Keeper\" is not one of Goalkeeper, Centre-Back, Left-Back, \
+                 Right-Back, Defensive Midfield, Central Midfield, Left Winger, Right Winger, \
+                 Second Striker, Centre-Forward",
+                "3:5: Kit Number: \"one\" is not a valid int",
+                "4:1: Name: duplicate value \"Wojciech Szczesny\"",
+                "4:3: DOB: \"1990-04-18\" does not match ^[A-Z][a-z]{2} \\d{1,2}, \\d{4} \\(\\d+\\)$",
+                "4:4: Nationality: required value is empty",
+                "4:5: Kit Number: 100 is greater than 99",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_columns() -> Result<()> {
+        let schema: CsvSchema = serde_yaml::from_str(
+            "strict: true\ncolumns:\n  - name: Name\n  - name: Age\n    type: int\n    required: true\n",
+        )?;
+        let errors = process_csv_validate(
+            "fixtures/players_semicolon.csv",
+            &schema,
+            &CsvReaderOpts {
+                delimiter: b';',
+                comment: Some(b'#'),
+                ..Default::default()
+            },
+        )?;
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            [
+                "2:0: missing required column \"Age\"",
+                "2:2: unexpected column \"Position\"",
+                "2:3: unexpected column \"Kit Number\"",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_malformed_rows() -> Result<()> {
+        let input = std::env::temp_dir().join("rcli_validate_malformed.csv");
+        fs::write(
+            &input,
+            b"Name,Age\nBuffon,46\nPerin\nPinsoglio,x\nSzcz\xe9sny,34\nHuijsen,y\n",
+        )?;
+        let schema: CsvSchema = serde_yaml::from_str("columns:\n  - name: Age\n    type: int\n")?;
+        let errors = process_csv_validate(input.to_str().unwrap(), &schema, &Default::default())?;
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            [
+                "3:0: expected 2 fields, found 1",
+                "4:2: Age: \"x\" is not a valid int",
+                "5:0: invalid UTF-8 byte sequence, the rest of the file is not checked",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_rejects_unknown_fields() {
+        let ret = serde_yaml::from_str::<CsvSchema>(
+            "columns:
+  - name: Age
+    requred: true
+",
+        );
+        assert!(ret.unwrap_err().to_string().contains("requred"));
+
+        let ret = serde_yaml::from_str::<CsvSchema>("strcit: true\ncolumns: []\n");
+        assert!(ret.unwrap_err().to_string().contains("strcit"));
+    }
+}
@@ -0,0 +1,399 @@
+use super::csv_arrow::ArrowRowWriter;
+use super::csv_flatten::flatten_row;
+use crate::{CsvReaderOpts, OutputFormat};
+use anyhow::Result;
+use csv::{Writer, WriterBuilder};
+use serde_json::{Map, Value};
+use std::io::{self, Seek, Write};
+
+/// Serialize rows one at a time so the whole dataset never has to be in memory
+pub trait RowWriter {
+    fn write_row(&mut self, row: &Value) -> Result<()>;
+    /// Write the closing part of the document and flush
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Create a streaming writer for `format`, `headers` is only used by csv and
+/// the columnar formats
+pub fn row_writer<'a, W: Write + 'a>(
+    writer: W,
+    format: OutputFormat,
+    headers: &[String],
+    opts: &CsvReaderOpts,
+) -> Result<Box<dyn RowWriter + 'a>> {
+    let writer: Box<dyn RowWriter + 'a> = match format {
+        OutputFormat::Json => Box::new(JsonArrayWriter { writer, rows: 0 }),
+        OutputFormat::Yaml => Box::new(YamlWriter { writer, rows: 0 }),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter { writer }),
+        OutputFormat::Toml => Box::new(TomlWriter { writer, rows: 0 }),
+        OutputFormat::Msgpack => Box::new(MsgpackWriter {
+            writer,
+            rows: tempfile::tempfile()?,
+            count: 0,
+        }),
+        OutputFormat::Cbor => Box::new(CborWriter::new(writer)?),
+        OutputFormat::Csv => Box::new(CsvRowWriter::new(writer, headers, opts)?),
+        OutputFormat::Parquet | OutputFormat::Arrow => {
+            Box::new(ArrowRowWriter::new(writer, format, headers)?)
+        }
+    };
+    Ok(writer)
+}
+
+/// `[` + pretty printed rows + `]`, same layout as `serde_json::to_string_pretty`
+struct JsonArrayWriter<W> {
+    writer: W,
+    rows: usize,
+}
+
+impl<W: Write> RowWriter for JsonArrayWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        self.writer
+            .write_all(if self.rows == 0 { b"[\n  " } else { b",\n  " })?;
+        let pretty = serde_json::to_string_pretty(row)?;
+        self.writer
+            .write_all(pretty.replace('\n', "\n  ").as_bytes())?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer
+            .write_all(if self.rows == 0 { b"[]" } else { b"\n]" })?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+struct NdjsonWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> RowWriter for NdjsonWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, row)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A yaml sequence, every row is emitted as its own `- ` item
+struct YamlWriter<W> {
+    writer: W,
+    rows: usize,
+}
+
+impl<W: Write> RowWriter for YamlWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        // 单元素序列序列化后就是 "- key: value\n  ...", 依次拼接仍是合法的序列
+        serde_yaml::to_writer(&mut self.writer, &[row])?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.rows == 0 {
+            self.writer.write_all(b"[]\n")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// An array of tables named `rows`
+struct TomlWriter<W> {
+    writer: W,
+    rows: usize,
+}
+
+impl<W: Write> RowWriter for TomlWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        // toml 的顶层必须是 table, 并且没有 null, 空值的字段直接省略
+        let table = serde_json::json!({ "rows": [strip_nulls(row.clone())] });
+        if self.rows > 0 {
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.write_all(toml::to_string(&table)?.as_bytes())?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.rows == 0 {
+            self.writer.write_all(b"rows = []\n")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// msgpack arrays need their length up front, rows are spooled to a temp file
+/// and copied after the header once the count is known.
+struct MsgpackWriter<W> {
+    writer: W,
+    rows: std::fs::File,
+    count: u32,
+}
+
+impl<W: Write> RowWriter for MsgpackWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        rmp_serde::encode::write_named(&mut self.rows, row)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        rmp::encode::write_array_len(&mut self.writer, self.count)?;
+        self.rows.rewind()?;
+        io::copy(&mut self.rows, &mut self.writer)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// cbor supports indefinite-length arrays, so rows can be written as they come
+struct CborWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> CborWriter<W> {
+    fn new(mut writer: W) -> Result<Self> {
+        // 0x9f: 不定长数组开始, 0xff: 结束
+        writer.write_all(&[0x9f])?;
+        Ok(Self { writer })
+    }
+}
+
+impl<W: Write> RowWriter for CborWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        ciborium::into_writer(row, &mut self.writer)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.write_all(&[0xff])?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+struct CsvRowWriter<W: Write> {
+    writer: Writer<W>,
+    headers: Vec<String>,
+}
+
+impl<W: Write> CsvRowWriter<W> {
+    fn new(writer: W, headers: &[String], opts: &CsvReaderOpts) -> Result<Self> {
+        let mut writer = csv_writer(writer, opts);
+        if opts.header {
+            writer.write_record(headers)?;
+        }
+        Ok(Self {
+            writer,
+            headers: headers.to_vec(),
+        })
+    }
+}
+
+impl<W: Write> RowWriter for CsvRowWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        let row = flatten_row(row.clone());
+        let record = self.headers.iter().map(|h| match row.get(h) {
+            Some(v) => cell_to_string(v, ";"),
+            None => String::new(),
+        });
+        self.writer.write_record(record)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Build a csv writer with the same dialect the reader side is configured with
+pub fn csv_writer<W: Write>(writer: W, opts: &CsvReaderOpts) -> Writer<W> {
+    let mut builder = WriterBuilder::new();
+    builder
+        .delimiter(opts.delimiter)
+        .quote(opts.quote)
+        .double_quote(opts.escape.is_none());
+    if let Some(escape) = opts.escape {
+        builder.escape(escape);
+    }
+    builder.from_writer(writer)
+}
+
+/// Write flat rows as csv, the header is written unless `--no-header` is set
+pub fn write_csv_rows<W: Write>(
+    writer: W,
+    headers: &[String],
+    rows: &[Map<String, Value>],
+    opts: &CsvReaderOpts,
+    array_separator: &str,
+) -> Result<()> {
+    let mut writer = csv_writer(writer, opts);
+    if opts.header {
+        writer.write_record(headers)?;
+    }
+    for row in rows {
+        let record = headers.iter().map(|h| match row.get(h) {
+            Some(v) => cell_to_string(v, array_separator),
+            None => String::new(),
+        });
+        writer.write_record(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Render a json value as a csv cell
+pub fn cell_to_string(value: &Value, array_separator: &str) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Bool(_) | Value::Number(_) => value.to_string(),
+        Value::Array(items) if items.iter().all(|v| !v.is_array() && !v.is_object()) => items
+            .iter()
+            .map(|v| cell_to_string(v, array_separator))
+            .collect::<Vec<_>>()
+            .join(array_separator),
+        // 嵌套的数组/对象无法展开成一个单元格, 保留为 json 字符串
+        Value::Array(_) | Value::Object(_) => value.to_string(),
+    }
+}
+
+pub fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => map
+            .into_iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k, strip_nulls(v)))
+            .collect(),
+        Value::Array(items) => items.into_iter().map(strip_nulls).collect(),
+        v => v,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rows() -> Vec<Value> {
+        vec![
+            json!({"Name": "Perin", "Kit Number": 37}),
+            json!({"Name": "Buffon", "Kit Number": null}),
+        ]
+    }
+
+    fn write_all(format: OutputFormat, rows: &[Value]) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        let headers = vec!["Name".to_string(), "Kit Number".to_string()];
+        let mut writer = row_writer(&mut buf, format, &headers, &CsvReaderOpts::default())?;
+        for row in rows {
+            writer.write_row(row)?;
+        }
+        writer.finish()?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn test_json_writer_matches_pretty() -> Result<()> {
+        let content = write_all(OutputFormat::Json, &rows())?;
+        assert_eq!(content, serde_json::to_vec_pretty(&rows())?);
+        assert_eq!(write_all(OutputFormat::Json, &[])?, b"[]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_writer_matches_sequence() -> Result<()> {
+        let content = write_all(OutputFormat::Yaml, &rows())?;
+        assert_eq!(String::from_utf8(content)?, serde_yaml::to_string(&rows())?);
+        let empty: Vec<Value> = serde_yaml::from_slice(&write_all(OutputFormat::Yaml, &[])?)?;
+        assert!(empty.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ndjson_writer() -> Result<()> {
+        let content = write_all(OutputFormat::Ndjson, &rows())?;
+        let lines: Vec<Value> = content
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(serde_json::from_slice)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines, rows());
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_writer() -> Result<()> {
+        let content = String::from_utf8(write_all(OutputFormat::Toml, &rows())?)?;
+        assert!(content.starts_with("[[rows]]"));
+        let parsed: toml::Value = toml::from_str(&content)?;
+        assert_eq!(parsed["rows"].as_array().unwrap().len(), 2);
+        assert!(parsed["rows"][1].get("Kit Number").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_writers() -> Result<()> {
+        let content = write_all(OutputFormat::Msgpack, &rows())?;
+        let decoded: Vec<Value> = rmp_serde::from_slice(&content)?;
+        assert_eq!(decoded, rows());
+
+        let content = write_all(OutputFormat::Cbor, &rows())?;
+        let decoded: Vec<Value> = ciborium::from_reader(content.as_slice())?;
+        assert_eq!(decoded, rows());
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_row_writer() -> Result<()> {
+        let content = write_all(OutputFormat::Csv, &rows())?;
+        assert_eq!(
+            String::from_utf8(content)?,
+            "Name,Kit Number\nPerin,37\nBuffon,\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cell_to_string() {
+        assert_eq!(cell_to_string(&json!(null), ";"), "");
+        assert_eq!(cell_to_string(&json!(37), ";"), "37");
+        assert_eq!(cell_to_string(&json!(["a", 1, true]), ";"), "a;1;true");
+        assert_eq!(cell_to_string(&json!([{"a": 1}]), ";"), r#"[{"a":1}]"#);
+    }
+
+    #[test]
+    fn test_write_csv_rows_with_dialect() -> Result<()> {
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            ..Default::default()
+        };
+        let headers = vec!["Name".to_string(), "Kit Number".to_string()];
+        let rows = vec![
+            json!({"Name": "Szczesny; Wojciech", "Kit Number": 1}),
+            json!({"Name": "Perin"}),
+        ];
+        let rows: Vec<_> = rows
+            .into_iter()
+            .map(|v| v.as_object().unwrap().clone())
+            .collect();
+        let mut buf = Vec::new();
+        write_csv_rows(&mut buf, &headers, &rows, &opts, ";")?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "Name;Kit Number\n\"Szczesny; Wojciech\";1\nPerin;\n"
+        );
+        Ok(())
+    }
+}
@@ -1,44 +1,489 @@
-use rand::prelude::SliceRandom;
-use rand::Rng;
-
-const UPPER: &[u8] = b"ABCDEFGHJKLMNOPQRSTUVWXYZ";
-const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
-const NUMBER: &[u8] = b"123456789";
-const SYMBOL: &[u8] = b"~!@#$%^&*_";
-
-pub fn process_genpass(
-    length: u8,
-    upper: bool,
-    lower: bool,
-    number: bool,
-    symbol: bool,
-) -> anyhow::Result<String> {
-    let mut rng = rand::thread_rng();
-    let mut password = Vec::new();
-    let mut chars = Vec::new();
-
-    if upper {
-        chars.extend_from_slice(UPPER);
-        let c = UPPER.choose(&mut rng).expect("UPPER won't be empty.");
-        password.push(*c);
-    }
-    if lower {
-        chars.extend_from_slice(LOWER);
-        password.push(*LOWER.choose(&mut rng).expect("LOWER won't be empty"));
-    }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).expect("NUMBER won't be empty"))
-    }
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).expect("SYMBOL won't be empty"))
-    }
-    for _ in 0..(length - password.len() as u8) {
-        let idx = rng.gen_range(0..chars.len());
-        password.push(chars[idx]);
-    }
-    password.shuffle(&mut rng);
-    let password = String::from_utf8(password)?;
-    Ok(password)
+use super::strength::{estimate_strength, StrengthReport};
+use crate::PasswordPolicy;
+use anyhow::{anyhow, Result};
+use rand::RngCore;
+use serde::Serialize;
+
+const UPPER: &str = "ABCDEFGHJKLMNOPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijkmnopqrstuvwxyz";
+const NUMBER: &str = "123456789";
+pub const DEFAULT_SYMBOLS: &str = "~!@#$%^&*_";
+/// Longest password `genpass` will generate
+pub const MAX_LENGTH: usize = 4096;
+/// Most passwords one `genpass --count` run will generate
+pub const MAX_COUNT: usize = 100_000;
+
+/// Restarts allowed when `--no-sequence` paints the generator into a corner
+const ATTEMPTS: usize = 100;
+/// Passwords tried per entry before `--min-score` gives up
+const SCORE_ATTEMPTS: usize = 1000;
+
+struct CharClass {
+    name: &'static str,
+    chars: Vec<char>,
+    min: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedPassword {
+    pub password: String,
+    /// Bits of entropy of the generator, see `policy_entropy`
+    pub entropy: f64,
+    #[serde(flatten)]
+    pub strength: StrengthReport,
+}
+
+pub fn process_genpass(policy: &PasswordPolicy) -> Result<String> {
+    generate_password(policy, &mut rand::thread_rng())
+}
+
+/// Generate `count` passwords with `generate`, which returns a password and
+/// its entropy, and hand each one to `emit` as soon as it is ready.
+/// Passwords zxcvbn scores below `min_score` are thrown away.
+pub fn process_genpass_batch(
+    count: usize,
+    min_score: Option<u8>,
+    mut generate: impl FnMut() -> Result<(String, f64)>,
+    mut emit: impl FnMut(GeneratedPassword) -> Result<()>,
+) -> Result<()> {
+    for _ in 0..count {
+        let mut attempts = 0;
+        let generated = loop {
+            let (password, entropy) = generate()?;
+            let strength = estimate_strength(&password, &[])?;
+            if min_score.is_none_or(|min| strength.score >= min) {
+                break GeneratedPassword {
+                    password,
+                    entropy,
+                    strength,
+                };
+            }
+            attempts += 1;
+            if attempts >= SCORE_ATTEMPTS {
+                return Err(anyhow!(
+                    "No password reached score {} in {} attempts, make the policy longer or richer",
+                    min_score.unwrap_or_default(),
+                    SCORE_ATTEMPTS
+                ));
+            }
+        };
+        emit(generated)?;
+    }
+    Ok(())
+}
+
+/// Bits of entropy of the character pool: `length * log2(pool)`, or the
+/// log2 of the number of arrangements with `--no-repeat`. Minimum counts
+/// and `--no-sequence` take off slightly less than a bit and are ignored.
+pub fn policy_entropy(policy: &PasswordPolicy) -> Result<f64> {
+    let pool: usize = char_classes(policy)?.iter().map(|c| c.chars.len()).sum();
+    let entropy = if policy.no_repeat {
+        (0..policy.length).map(|i| ((pool - i) as f64).log2()).sum()
+    } else {
+        policy.length as f64 * (pool as f64).log2()
+    };
+    Ok(entropy)
+}
+
+/// Generate a password satisfying `policy`, drawing randomness from `rng`.
+///
+/// Only `next_u32` of `rng` is used, through `uniform`, so the output for a
+/// given stream of words is fixed. `genpass derive` relies on this.
+pub fn generate_password<R: RngCore + ?Sized>(
+    policy: &PasswordPolicy,
+    rng: &mut R,
+) -> Result<String> {
+    let classes = char_classes(policy)?;
+    let all: Vec<char> = classes.iter().flat_map(|c| c.chars.clone()).collect();
+    for _ in 0..ATTEMPTS {
+        // 先按每类的最小数量取字符, 剩下的从全部字符中取
+        let mut picked = Vec::with_capacity(policy.length);
+        for class in &classes {
+            picked.extend(draw(&class.chars, class.min, policy.no_repeat, rng));
+        }
+        let pool: Vec<char> = all
+            .iter()
+            .copied()
+            .filter(|c| !policy.no_repeat || !picked.contains(c))
+            .collect();
+        picked.extend(draw(
+            &pool,
+            policy.length - picked.len(),
+            policy.no_repeat,
+            rng,
+        ));
+        if let Some(password) = arrange(picked, policy.no_sequence, rng) {
+            return Ok(password.into_iter().collect());
+        }
+    }
+    Err(anyhow!(
+        "Could not avoid sequences with these characters, allow more characters or drop --no-sequence"
+    ))
+}
+
+// 校验策略并算出每一类实际可用的字符
+fn char_classes(policy: &PasswordPolicy) -> Result<Vec<CharClass>> {
+    if policy.length == 0 || policy.length > MAX_LENGTH {
+        return Err(anyhow!(
+            "Length must be between 1 and {}, got {}",
+            MAX_LENGTH,
+            policy.length
+        ));
+    }
+    let mut symbols: Vec<char> = Vec::new();
+    for c in policy.symbol_set.chars() {
+        if c.is_whitespace() || c.is_control() {
+            return Err(anyhow!(
+                "--symbol-set can't contain whitespace or control characters"
+            ));
+        }
+        if c.is_alphanumeric() {
+            return Err(anyhow!(
+                "--symbol-set can't contain letters or digits: {:?}",
+                c
+            ));
+        }
+        if !symbols.contains(&c) {
+            symbols.push(c);
+        }
+    }
+    let classes: Vec<CharClass> = [
+        char_class(
+            policy,
+            "uppercase",
+            "uppercase",
+            policy.uppercase,
+            UPPER.chars(),
+            policy.min_uppercase,
+        )?,
+        char_class(
+            policy,
+            "lowercase",
+            "lowercase",
+            policy.lowercase,
+            LOWER.chars(),
+            policy.min_lowercase,
+        )?,
+        char_class(
+            policy,
+            "number",
+            "numbers",
+            policy.numbers,
+            NUMBER.chars(),
+            policy.min_numbers,
+        )?,
+        char_class(
+            policy,
+            "symbol",
+            "symbols",
+            policy.symbols,
+            symbols.into_iter(),
+            policy.min_symbols,
+        )?,
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if classes.is_empty() {
+        return Err(anyhow!("No characters left to build a password from"));
+    }
+    let required: usize = classes.iter().map(|c| c.min).sum();
+    if required > policy.length {
+        let mins: Vec<String> = classes
+            .iter()
+            .filter(|c| c.min > 0)
+            .map(|c| format!("{} {}", c.min, c.name))
+            .collect();
+        return Err(anyhow!(
+            "Length {} is too short for at least {}",
+            policy.length,
+            mins.join(", ")
+        ));
+    }
+    let available: usize = classes.iter().map(|c| c.chars.len()).sum();
+    if policy.no_repeat && policy.length > available {
+        return Err(anyhow!(
+            "--no-repeat allows at most {} characters with this policy",
+            available
+        ));
+    }
+    Ok(classes)
+}
+
+// `flag` 是命令行参数里的名字, 比如 --no-numbers / --min-numbers
+fn char_class(
+    policy: &PasswordPolicy,
+    name: &'static str,
+    flag: &str,
+    enabled: bool,
+    chars: impl Iterator<Item = char>,
+    min: Option<usize>,
+) -> Result<Option<CharClass>> {
+    if !enabled {
+        if min.is_some_and(|n| n > 0) {
+            return Err(anyhow!("--min-{} conflicts with --no-{}", flag, flag));
+        }
+        return Ok(None);
+    }
+    let chars: Vec<char> = chars.filter(|c| !policy.exclude.contains(*c)).collect();
+    if chars.is_empty() {
+        if min.is_some_and(|n| n > 0) {
+            return Err(anyhow!("No {} characters left after --exclude", name));
+        }
+        // 整类都被排除了, 默认的最少 1 个不再要求
+        return Ok(None);
+    }
+    let min = min.unwrap_or(1);
+    if policy.no_repeat && min > chars.len() {
+        return Err(anyhow!(
+            "--min-{} {} needs repeated characters, only {} are available",
+            flag,
+            min,
+            chars.len()
+        ));
+    }
+    Ok(Some(CharClass { name, chars, min }))
+}
+
+/// A uniform index below `n`: `next_u32` words of `rng` at or above the
+/// largest multiple of `n` are rejected, the first other one is taken mod `n`
+fn uniform<R: RngCore + ?Sized>(rng: &mut R, n: usize) -> usize {
+    let n = n as u64;
+    let limit = (1u64 << 32) - (1u64 << 32) % n;
+    loop {
+        let x = rng.next_u32() as u64;
+        if x < limit {
+            return (x % n) as usize;
+        }
+    }
+}
+
+// 不重复时是部分 Fisher-Yates: 第 k 个和 k..len 中随机一个交换
+fn draw<R: RngCore + ?Sized>(chars: &[char], n: usize, unique: bool, rng: &mut R) -> Vec<char> {
+    if !unique {
+        return (0..n).map(|_| chars[uniform(rng, chars.len())]).collect();
+    }
+    let mut chars = chars.to_vec();
+    for k in 0..n {
+        let j = k + uniform(rng, chars.len() - k);
+        chars.swap(k, j);
+    }
+    chars.truncate(n);
+    chars
+}
+
+// Fisher-Yates 洗牌; 需要避免连续序列时逐个挑选不会形成序列的字符, 走投无路返回 None
+fn arrange<R: RngCore + ?Sized>(
+    mut chars: Vec<char>,
+    no_sequence: bool,
+    rng: &mut R,
+) -> Option<Vec<char>> {
+    for i in (1..chars.len()).rev() {
+        let j = uniform(rng, i + 1);
+        chars.swap(i, j);
+    }
+    if !no_sequence {
+        return Some(chars);
+    }
+    let mut password: Vec<char> = Vec::with_capacity(chars.len());
+    while !chars.is_empty() {
+        let fits: Vec<usize> = (0..chars.len())
+            .filter(|&i| !is_sequence(&password, chars[i]))
+            .collect();
+        if fits.is_empty() {
+            return None;
+        }
+        let i = fits[uniform(rng, fits.len())];
+        password.push(chars.swap_remove(i));
+    }
+    Some(password)
+}
+
+/// Whether `next` makes the last three characters a run like "abc" or "321"
+fn is_sequence(password: &[char], next: char) -> bool {
+    let [.., a, b] = password else {
+        return false;
+    };
+    let (a, b, c) = (*a as i64, *b as i64, next as i64);
+    b - a == c - b && (c - b).abs() == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy::default()
+    }
+
+    #[test]
+    fn test_genpass_policy() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let p = PasswordPolicy {
+            length: 12,
+            symbols: false,
+            min_numbers: Some(4),
+            exclude: "abc".to_string(),
+            ..policy()
+        };
+        for _ in 0..50 {
+            let password = generate_password(&p, &mut rng)?;
+            assert_eq!(password.chars().count(), 12);
+            assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+            assert!(password.chars().filter(|c| c.is_ascii_digit()).count() >= 4);
+            assert!(!password.contains(['a', 'b', 'c']));
+        }
+
+        let p = PasswordPolicy {
+            length: 9,
+            uppercase: false,
+            lowercase: false,
+            symbols: false,
+            no_repeat: true,
+            no_sequence: true,
+            ..policy()
+        };
+        let password: Vec<char> = generate_password(&p, &mut rng)?.chars().collect();
+        let mut sorted = password.clone();
+        sorted.sort();
+        assert_eq!(sorted.iter().collect::<String>(), NUMBER);
+        assert!(password.windows(3).all(|w| !is_sequence(&w[..2], w[2])));
+
+        let long = PasswordPolicy {
+            length: 1000,
+            symbol_set: "-+".to_string(),
+            ..policy()
+        };
+        assert_eq!(generate_password(&long, &mut rng)?.len(), 1000);
+        Ok(())
+    }
+
+    // 把固定的 32 位字序列当成 rng, 只实现 next_u32
+    struct Words<I>(I);
+
+    impl<I: Iterator<Item = u32>> RngCore for Words<I> {
+        fn next_u32(&mut self) -> u32 {
+            self.0.next().expect("ran out of words")
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            unimplemented!("generate_password only reads next_u32")
+        }
+
+        fn fill_bytes(&mut self, _: &mut [u8]) {
+            unimplemented!("generate_password only reads next_u32")
+        }
+
+        fn try_fill_bytes(&mut self, _: &mut [u8]) -> Result<(), rand::Error> {
+            unimplemented!("generate_password only reads next_u32")
+        }
+    }
+
+    // 0, φ·2^32, 2φ·2^32, ... (mod 2^32)
+    fn weyl() -> Words<impl Iterator<Item = u32>> {
+        Words(std::iter::successors(Some(0u32), |x| {
+            Some(x.wrapping_add(0x9E37_79B9))
+        }))
+    }
+
+    // genpass derive 的输出取决于这里的每一步, 改了字符表、取字符的顺序或者
+    // uniform/draw/arrange 都会让这个测试失败; 期望值来自
+    // scripts/genpass_derive_vectors.py 里的 generate()
+    #[test]
+    fn test_generate_fixed_stream() -> Result<()> {
+        assert_eq!(
+            generate_password(&policy(), &mut weyl())?,
+            "3!O#m9eUuDvpK6kA"
+        );
+        let p = PasswordPolicy {
+            length: 20,
+            symbol_set: "-_.".to_string(),
+            exclude: "0O1lI".to_string(),
+            min_numbers: Some(3),
+            no_repeat: true,
+            no_sequence: true,
+            ..policy()
+        };
+        assert_eq!(generate_password(&p, &mut weyl())?, "s28y-47DVub3CmANWvJf");
+
+        // 2^32 mod 3 = 1, 最大的那个 u32 被拒绝, 取下一个
+        let mut words = Words([u32::MAX, 5].into_iter());
+        assert_eq!(uniform(&mut words, 3), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_genpass_batch_min_score() -> Result<()> {
+        let p = policy();
+        assert_eq!(policy_entropy(&p)?, 16.0 * 69f64.log2());
+        let mut passwords = Vec::new();
+        process_genpass_batch(
+            3,
+            Some(4),
+            || Ok((process_genpass(&p)?, policy_entropy(&p)?)),
+            |generated| {
+                passwords.push(generated);
+                Ok(())
+            },
+        )?;
+        assert_eq!(passwords.len(), 3);
+        assert!(passwords.iter().all(|p| p.strength.score == 4));
+
+        let short = PasswordPolicy {
+            length: 4,
+            ..policy()
+        };
+        let err = process_genpass_batch(
+            1,
+            Some(4),
+            || Ok((process_genpass(&short)?, 0.0)),
+            |_| Ok(()),
+        );
+        assert!(err.unwrap_err().to_string().contains("score 4"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_genpass_invalid_policy() {
+        let err = |p: PasswordPolicy| process_genpass(&p).unwrap_err().to_string();
+        assert!(err(PasswordPolicy {
+            length: 3,
+            ..policy()
+        })
+        .contains("too short"));
+        assert!(err(PasswordPolicy {
+            length: 0,
+            ..policy()
+        })
+        .contains("between 1"));
+        assert!(err(PasswordPolicy {
+            symbols: false,
+            min_symbols: Some(2),
+            ..policy()
+        })
+        .contains("--no-symbols"));
+        assert!(err(PasswordPolicy {
+            uppercase: false,
+            lowercase: false,
+            numbers: false,
+            symbols: false,
+            ..policy()
+        })
+        .contains("No characters"));
+        assert!(err(PasswordPolicy {
+            length: 40,
+            no_repeat: true,
+            uppercase: false,
+            lowercase: false,
+            ..policy()
+        })
+        .contains("at most 19"));
+        assert!(err(PasswordPolicy {
+            symbol_set: "a!".to_string(),
+            ..policy()
+        })
+        .contains("letters or digits"));
+    }
 }
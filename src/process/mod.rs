@@ -1,15 +1,62 @@
 mod b64;
+mod csv_arrow;
 mod csv_convert;
+mod csv_diff;
+mod csv_encoding;
+mod csv_fake;
+mod csv_filter;
+mod csv_flatten;
+mod csv_group;
+mod csv_merge;
+mod csv_query;
+mod csv_reader;
+mod csv_render;
+mod csv_show;
+mod csv_sort;
+mod csv_split;
+mod csv_stats;
+mod csv_types;
+mod csv_validate;
+mod csv_writer;
+mod derive_pass;
 mod gen_pass;
 mod http_serve;
 mod jwt;
+mod passphrase;
+mod password_check;
+mod strength;
 mod text;
 
 pub use b64::{process_decode, process_encode};
-pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use csv_convert::{process_csv, process_to_csv};
+pub use csv_diff::{diff_csv, write_diff, CsvDiff, DiffSummary, FieldChange, RowChange};
+pub use csv_fake::process_csv_fake;
+pub use csv_filter::{Expr, SortKey};
+pub use csv_group::{process_csv_group, process_csv_pivot, AggFunc, Aggregate};
+pub use csv_merge::{process_csv_cat, process_csv_dedup, process_csv_join};
+pub use csv_query::{process_csv_query, write_query_result, QueryResult};
+pub use csv_render::process_csv_render;
+pub use csv_show::{process_csv_show, render_table};
+pub use csv_sort::DEFAULT_SORT_BUFFER;
+pub use csv_split::{
+    process_csv_head, process_csv_sample, process_csv_split_by, process_csv_split_rows,
+    process_csv_tail,
+};
+pub use csv_stats::{process_csv_stats, render_stats, ColumnStats, ValueCount};
+pub use csv_validate::{process_csv_validate, CsvSchema, ValidationError};
+pub use derive_pass::{derive_seed, process_genpass_derive, read_master, DERIVE_CONTEXT};
+pub use gen_pass::{
+    generate_password, policy_entropy, process_genpass, process_genpass_batch, GeneratedPassword,
+    DEFAULT_SYMBOLS, MAX_COUNT, MAX_LENGTH,
+};
 pub use http_serve::process_http_serve;
 pub use jwt::{process_jwt_sign, process_jwt_verify};
+pub use passphrase::{generate_passphrase, load_wordlist, Passphrase, MAX_WORDS};
+pub use password_check::{process_password_check, read_passwords, PasswordCheck};
+pub use strength::{
+    estimate_strength, estimate_strength_redacted, CrackTime, CrackTimes, Feedback, MatchReport,
+    StrengthReport,
+};
 pub use text::{
     process_text_decrypt, process_text_encrypt, process_text_generate, process_text_sign,
     process_text_verify,
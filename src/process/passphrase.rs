@@ -0,0 +1,156 @@
+use crate::PassphraseOpts;
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use std::collections::HashSet;
+use std::fs;
+
+/// The BIP-39 English list, 2048 words (11 bits each), see assets/README.md
+const BUILTIN_WORDS: &str = include_str!("../../assets/wordlists/bip39_english.txt");
+
+/// Most words a passphrase may have
+pub const MAX_WORDS: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Passphrase {
+    pub phrase: String,
+    /// Bits of entropy given the wordlist and options, not an estimate
+    pub entropy: f64,
+}
+
+/// One word per line; EFF dice lists ("11111\tabacus") are accepted as is.
+/// Without a path the built-in list is used. Duplicates are dropped so the
+/// entropy is not overstated.
+pub fn load_wordlist(path: Option<&str>) -> Result<Vec<String>> {
+    let content = match path {
+        Some(path) => fs::read_to_string(path)?,
+        None => BUILTIN_WORDS.to_string(),
+    };
+    let mut seen = HashSet::new();
+    let words: Vec<String> = content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some(dice), Some(word)) if dice.chars().all(|c| c.is_ascii_digit()) => Some(word),
+                (Some(word), None) => Some(word),
+                _ => None,
+            }
+        })
+        .filter(|word| seen.insert(word.to_string()))
+        .map(String::from)
+        .collect();
+    if words.len() < 2 {
+        return Err(anyhow!(
+            "{}: need at least 2 distinct words, found {}",
+            path.unwrap_or("wordlist"),
+            words.len()
+        ));
+    }
+    Ok(words)
+}
+
+/// Pick `opts.words` words uniformly from `words`. `--add-digit` appends a
+/// random digit to one random word.
+pub fn generate_passphrase<R: Rng + ?Sized>(
+    words: &[String],
+    opts: &PassphraseOpts,
+    rng: &mut R,
+) -> Result<Passphrase> {
+    if opts.words == 0 || opts.words > MAX_WORDS {
+        return Err(anyhow!(
+            "--words must be between 1 and {}, got {}",
+            MAX_WORDS,
+            opts.words
+        ));
+    }
+    let mut picked: Vec<String> = (0..opts.words)
+        .map(|_| words[rng.gen_range(0..words.len())].clone())
+        .map(|word| {
+            if opts.capitalize {
+                capitalize(&word)
+            } else {
+                word
+            }
+        })
+        .collect();
+    let mut entropy = opts.words as f64 * (words.len() as f64).log2();
+    if opts.add_digit {
+        let i = rng.gen_range(0..picked.len());
+        picked[i].push(char::from(b'0' + rng.gen_range(0..10)));
+        // 数字本身 10 种, 加在哪个词后面 words 种
+        entropy += (10.0 * opts.words as f64).log2();
+    }
+    Ok(Passphrase {
+        phrase: picked.join(&opts.separator),
+        entropy,
+    })
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn opts() -> PassphraseOpts {
+        PassphraseOpts {
+            words: 6,
+            separator: "-".to_string(),
+            capitalize: false,
+            add_digit: false,
+            wordlist: None,
+        }
+    }
+
+    #[test]
+    fn test_passphrase() -> Result<()> {
+        let words = load_wordlist(None)?;
+        assert_eq!(words.len(), 2048);
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let p = generate_passphrase(&words, &opts(), &mut rng)?;
+        assert_eq!(p.entropy, 66.0);
+        let parts: Vec<&str> = p.phrase.split('-').collect();
+        assert_eq!(parts.len(), 6);
+        assert!(parts.iter().all(|w| words.iter().any(|x| x == w)));
+
+        let o = PassphraseOpts {
+            capitalize: true,
+            add_digit: true,
+            separator: " ".to_string(),
+            ..opts()
+        };
+        let p = generate_passphrase(&words, &o, &mut rng)?;
+        assert!((p.entropy - (66.0 + 60f64.log2())).abs() < 1e-9);
+        assert!(p
+            .phrase
+            .split(' ')
+            .all(|w| w.starts_with(char::is_uppercase)));
+        assert_eq!(p.phrase.chars().filter(char::is_ascii_digit).count(), 1);
+
+        let zero = PassphraseOpts { words: 0, ..opts() };
+        assert!(generate_passphrase(&words, &zero, &mut rng).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_eff_wordlist() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("eff.txt");
+        fs::write(&path, "11111\tabacus\n11112\tabdomen\n11113\tabacus\n\n")?;
+        let words = load_wordlist(Some(&path.to_string_lossy()))?;
+        assert_eq!(words, ["abacus", "abdomen"]);
+
+        fs::write(&path, "only\n")?;
+        assert!(load_wordlist(Some(&path.to_string_lossy())).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use super::strength::{estimate_strength_redacted, StrengthReport};
+use crate::get_reader;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordCheck {
+    /// Line of the input, neither the password nor parts of it are reported
+    pub line: usize,
+    /// Score reaches the threshold and the password is not breached
+    pub passed: bool,
+    /// Times seen in the breach list, `None` when no list was given
+    pub breached: Option<u64>,
+    #[serde(flatten)]
+    pub strength: StrengthReport,
+}
+
+/// Passwords with their line numbers, from a file or stdin (`-`) with one
+/// per line, or from a prompt that doesn't echo when there is no input.
+/// Blank (or whitespace-only) lines are skipped but still counted.
+pub fn read_passwords(input: Option<&str>) -> Result<Vec<(usize, String)>> {
+    let Some(input) = input else {
+        let password = rpassword::prompt_password("Password: ")?;
+        if password.is_empty() {
+            return Err(anyhow!("Empty password"));
+        }
+        return Ok(vec![(1, password)]);
+    };
+    let mut content = String::new();
+    get_reader(input)?.read_to_string(&mut content)?;
+    Ok(content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim_end_matches('\r').to_string()))
+        .filter(|(_, line)| !line.trim().is_empty())
+        .collect())
+}
+
+/// Run zxcvbn on every password and look them up in `breach_file`. A
+/// password passes when it scores at least `min_score` and is not breached.
+pub fn process_password_check(
+    passwords: &[(usize, String)],
+    user_inputs: &[String],
+    breach_file: Option<&str>,
+    min_score: u8,
+) -> Result<Vec<PasswordCheck>> {
+    let user_inputs: Vec<&str> = user_inputs.iter().map(|s| s.as_str()).collect();
+    let hashes: Vec<String> = passwords.iter().map(|(_, p)| sha1_hex(p)).collect();
+    let breaches = match breach_file {
+        Some(path) => Some(breach_counts(Path::new(path), &hashes)?),
+        None => None,
+    };
+    passwords
+        .iter()
+        .zip(&hashes)
+        .map(|((line, password), hash)| {
+            // 报告可能进 CI 日志, 不输出密码片段, 只保留位置
+            let strength = estimate_strength_redacted(password, &user_inputs)
+                .map_err(|e| anyhow!("line {}: {}", line, e))?;
+            let breached = breaches
+                .as_ref()
+                .map(|b| b.get(hash).copied().unwrap_or_default());
+            Ok(PasswordCheck {
+                line: *line,
+                passed: strength.score >= min_score && breached.unwrap_or_default() == 0,
+                breached,
+                strength,
+            })
+        })
+        .collect()
+}
+
+fn sha1_hex(password: &str) -> String {
+    Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect()
+}
+
+// 两种 HIBP 格式: 一个 "HASH:COUNT" 的大文件, 或者按 5 位前缀命名的 range 文件目录
+// (每行是去掉前缀的 35 位 "SUFFIX:COUNT")
+fn breach_counts(path: &Path, hashes: &[String]) -> Result<HashMap<String, u64>> {
+    let mut found = HashMap::new();
+    // 完整的 HIBP 文件有上亿行, 每行都要查一次
+    let wanted: HashSet<&str> = hashes.iter().map(|h| h.as_str()).collect();
+    if !path.is_dir() {
+        scan_breach_file(path, &wanted, &mut found)?;
+        return Ok(found);
+    }
+    let mut prefixes: Vec<&str> = hashes.iter().map(|h| &h[..5]).collect();
+    prefixes.sort();
+    prefixes.dedup();
+    for prefix in prefixes {
+        let file = [format!("{}.txt", prefix), prefix.to_string()]
+            .into_iter()
+            .map(|name| path.join(name))
+            .find(|file| file.exists());
+        if let Some(file) = file {
+            scan_breach_file(&file, &wanted, &mut found)?;
+        }
+    }
+    Ok(found)
+}
+
+// 单个文件从头扫到尾, 所有密码只扫一遍
+fn scan_breach_file(
+    path: &Path,
+    hashes: &HashSet<&str>,
+    found: &mut HashMap<String, u64>,
+) -> Result<()> {
+    // range 文件的文件名就是前缀
+    let prefix = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_uppercase())
+        .filter(|s| s.len() == 5 && s.chars().all(|c| c.is_ascii_hexdigit()))
+        .unwrap_or_default();
+    let reader = BufReader::new(File::open(path)?);
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (hash, count) = line.split_once(':').unwrap_or((line, "1"));
+        let hash = match hash.len() {
+            40 => hash.to_uppercase(),
+            35 if !prefix.is_empty() => format!("{}{}", prefix, hash.to_uppercase()),
+            _ => {
+                return Err(anyhow!(
+                    "{}:{}: expect a SHA-1 hash (or a 35 character suffix in a file named by its prefix)",
+                    path.display(),
+                    i + 1
+                ))
+            }
+        };
+        if hashes.contains(hash.as_str()) {
+            let count = count
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("{}:{}: invalid count {:?}", path.display(), i + 1, count))?;
+            found.insert(hash, count);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn passwords() -> Result<Vec<(usize, String)>> {
+        read_passwords(Some("fixtures/passwords.txt"))
+    }
+
+    fn lines(passwords: &[&str]) -> Vec<(usize, String)> {
+        passwords
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i + 1, p.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_password_check() -> Result<()> {
+        let passwords = passwords()?;
+        assert_eq!(passwords.len(), 3);
+        let checks = process_password_check(&passwords, &[], Some("fixtures/pwned.txt"), 3)?;
+        assert_eq!(checks[0].breached, Some(3861493));
+        assert!(!checks[0].passed);
+        assert_eq!(checks[0].strength.score, 0);
+        assert_eq!(checks[2].breached, Some(0));
+        assert!(checks[2].passed);
+
+        let unchecked = process_password_check(&passwords, &[], None, 0)?;
+        assert!(unchecked.iter().all(|c| c.breached.is_none() && c.passed));
+
+        // 太长的密码 guesses 会饱和, zxcvbn 选哪种匹配就不确定了, 这里用短的
+        let personal = vec!["szczesny".to_string()];
+        let checks = process_password_check(&lines(&["Szczesny2017"]), &personal, None, 0)?;
+        let first = &checks[0].strength.sequence[0];
+        assert!(first.detail.starts_with("user_inputs"), "{:?}", first);
+        assert!(first.token.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_password_check_line_numbers() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("passwords.txt");
+        fs::write(&input, "hunter\n \t\nzzzzzzzzzzzzqq\n")?;
+        let passwords = read_passwords(Some(&input.to_string_lossy()))?;
+        let checks = process_password_check(&passwords, &[], None, 0)?;
+        let numbers: Vec<_> = checks.iter().map(|c| c.line).collect();
+        assert_eq!(numbers, [1, 3]);
+
+        fs::write(&input, "\n  \n")?;
+        assert!(read_passwords(Some(&input.to_string_lossy()))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_does_not_leak_passwords() -> Result<()> {
+        let passwords = [
+            "zzzzzzzzzzzzqq",
+            "buffon18041990",
+            "Tr0ub4dor&3",
+            "qwertyuiop",
+        ];
+        let checks = process_password_check(&lines(&passwords), &[], None, 0)?;
+        let json = serde_json::to_string(&checks)?.to_lowercase();
+        for password in passwords {
+            let chars: Vec<char> = password.to_lowercase().chars().collect();
+            for part in chars.windows(3) {
+                let part: String = part.iter().collect();
+                assert!(!json.contains(&part), "{:?} leaks in {}", part, json);
+            }
+        }
+        // 重复的字符和日期也不能出现
+        assert!(!json.contains("\"z\""), "{}", json);
+        assert!(!json.contains("1990"), "{}", json);
+        Ok(())
+    }
+
+    #[test]
+    fn test_breach_range_directory() -> Result<()> {
+        let checks = process_password_check(&passwords()?, &[], Some("fixtures/pwned_range"), 0)?;
+        let breached: Vec<_> = checks.iter().map(|c| c.breached).collect();
+        assert_eq!(breached, [Some(3861493), Some(0), Some(0)]);
+        assert_eq!(
+            sha1_hex("password"),
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"
+        );
+        Ok(())
+    }
+}
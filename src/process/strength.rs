@@ -0,0 +1,208 @@
+use anyhow::Result;
+use serde::Serialize;
+use zxcvbn::matching::patterns::MatchPattern;
+use zxcvbn::time_estimates::CrackTimeSeconds;
+use zxcvbn::{zxcvbn, Match};
+
+/// What zxcvbn thinks of a password, ready to be printed or serialized
+#[derive(Debug, Clone, Serialize)]
+pub struct StrengthReport {
+    /// 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    pub guesses: u64,
+    pub guesses_log10: f64,
+    pub crack_times: CrackTimes,
+    pub feedback: Feedback,
+    /// How zxcvbn would guess the password, piece by piece
+    pub sequence: Vec<MatchReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchReport {
+    /// The matched part of the password, left empty when it must not leak
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub token: String,
+    /// First and last character of the match, counting from 0
+    pub start: usize,
+    pub end: usize,
+    /// dictionary, spatial, repeat, sequence, regex, date or bruteforce
+    pub pattern: &'static str,
+    /// What matched, e.g. "passwords #2, l33t" or "qwerty, 1 turn"
+    pub detail: String,
+    pub guesses: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrackTimes {
+    pub online_throttling_100_per_hour: CrackTime,
+    pub online_no_throttling_10_per_second: CrackTime,
+    pub offline_slow_hashing_1e4_per_second: CrackTime,
+    pub offline_fast_hashing_1e10_per_second: CrackTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrackTime {
+    pub seconds: f64,
+    /// e.g. "3 hours", "centuries"
+    pub display: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Feedback {
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+/// Estimate with zxcvbn, `user_inputs` are words the password should not be
+/// built from (user name, site, ...)
+pub fn estimate_strength(password: &str, user_inputs: &[&str]) -> Result<StrengthReport> {
+    estimate(password, user_inputs, false)
+}
+
+/// Like `estimate_strength`, but nothing in the report gives away the
+/// password: no tokens, repeated parts, dates or dictionary ranks
+pub fn estimate_strength_redacted(password: &str, user_inputs: &[&str]) -> Result<StrengthReport> {
+    estimate(password, user_inputs, true)
+}
+
+fn estimate(password: &str, user_inputs: &[&str], redact: bool) -> Result<StrengthReport> {
+    let estimate = zxcvbn(password, user_inputs)?;
+    let times = estimate.crack_times();
+    let feedback = match estimate.feedback() {
+        Some(feedback) => Feedback {
+            warning: feedback.warning().map(|w| w.to_string()),
+            suggestions: feedback
+                .suggestions()
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        },
+        None => Feedback::default(),
+    };
+    Ok(StrengthReport {
+        score: estimate.score(),
+        guesses: estimate.guesses(),
+        guesses_log10: estimate.guesses_log10(),
+        crack_times: CrackTimes {
+            online_throttling_100_per_hour: times.online_throttling_100_per_hour().into(),
+            online_no_throttling_10_per_second: times.online_no_throttling_10_per_second().into(),
+            offline_slow_hashing_1e4_per_second: times.offline_slow_hashing_1e4_per_second().into(),
+            offline_fast_hashing_1e10_per_second: times
+                .offline_fast_hashing_1e10_per_second()
+                .into(),
+        },
+        feedback,
+        sequence: estimate
+            .sequence()
+            .iter()
+            .map(|m| MatchReport::new(m, redact))
+            .collect(),
+    })
+}
+
+impl MatchReport {
+    // 公开词典里的排名也能还原出单词, redact 时和 token 一起去掉
+    fn new(m: &Match, redact: bool) -> Self {
+        let (pattern, detail) = match &m.pattern {
+            MatchPattern::Dictionary(d) => {
+                let mut detail = snake_case(&format!("{:?}", d.dictionary_name));
+                if !redact {
+                    detail.push_str(&format!(" #{}", d.rank));
+                }
+                if d.reversed {
+                    detail.push_str(", reversed");
+                }
+                if d.l33t {
+                    detail.push_str(", l33t");
+                }
+                ("dictionary", detail)
+            }
+            MatchPattern::Spatial(s) => (
+                "spatial",
+                format!(
+                    "{}, {} turn{}",
+                    s.graph,
+                    s.turns,
+                    if s.turns == 1 { "" } else { "s" }
+                ),
+            ),
+            MatchPattern::Repeat(r) if redact => ("repeat", format!("x{}", r.repeat_count)),
+            MatchPattern::Repeat(r) => {
+                ("repeat", format!("{:?} x{}", r.base_token, r.repeat_count))
+            }
+            MatchPattern::Sequence(s) => (
+                "sequence",
+                format!(
+                    "{}, {}",
+                    s.sequence_name,
+                    if s.ascending {
+                        "ascending"
+                    } else {
+                        "descending"
+                    }
+                ),
+            ),
+            MatchPattern::Regex(r) => ("regex", r.regex_name.to_string()),
+            MatchPattern::Date(_) if redact => ("date", String::new()),
+            MatchPattern::Date(d) => ("date", format!("{:04}-{:02}-{:02}", d.year, d.month, d.day)),
+            MatchPattern::BruteForce => ("bruteforce", String::new()),
+        };
+        Self {
+            token: if redact {
+                String::new()
+            } else {
+                m.token.clone()
+            },
+            start: m.i,
+            end: m.j,
+            pattern,
+            detail,
+            guesses: m.guesses,
+        }
+    }
+}
+
+// zxcvbn 没有公开词典类型, 只能从 Debug 输出转换: UsTvAndFilm -> us_tv_and_film
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+impl From<CrackTimeSeconds> for CrackTime {
+    fn from(value: CrackTimeSeconds) -> Self {
+        let seconds = match value {
+            CrackTimeSeconds::Integer(s) => s as f64,
+            CrackTimeSeconds::Float(s) => s,
+        };
+        Self {
+            seconds,
+            display: value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_strength() -> Result<()> {
+        let weak = estimate_strength("P@ssw0rd123", &[])?;
+        assert!(weak.score <= 1);
+        assert!(weak.feedback.warning.is_some());
+        let first = &weak.sequence[0];
+        assert_eq!(first.pattern, "dictionary");
+        assert!(first.detail.starts_with("passwords #"), "{}", first.detail);
+
+        let personal = estimate_strength("perin1992", &["perin"])?;
+        assert!(personal.sequence[0].detail.starts_with("user_inputs"));
+        assert_eq!(snake_case("UsTvAndFilm"), "us_tv_and_film");
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{get_reader, process_genpass, TextEncryptFormat, TextSignFormat};
+use crate::{get_reader, process_genpass, PasswordPolicy, TextEncryptFormat, TextSignFormat};
 use anyhow::{anyhow, Result};
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use base64::Engine;
@@ -124,7 +124,10 @@ impl KeyLoader for Blake3 {
 
 impl KeyGenerator for Blake3 {
     fn generate() -> Result<Vec<Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
+        let key = process_genpass(&PasswordPolicy {
+            length: 32,
+            ..Default::default()
+        })?;
         let key = key.as_bytes().to_vec();
         Ok(vec![key])
     }
@@ -1,5 +1,9 @@
-use std::fs::File;
-use std::io::Read;
+use anyhow::anyhow;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tempfile::TempPath;
 
 pub fn get_reader(input: &str) -> anyhow::Result<Box<dyn Read>> {
     // 两种不同的数据类型 stdin 和 File 出现在同一个表达式 if...else 中
@@ -11,3 +15,189 @@ pub fn get_reader(input: &str) -> anyhow::Result<Box<dyn Read>> {
     };
     Ok(reader)
 }
+
+/// An output file written atomically: rows go to a temporary file next to
+/// it, which `commit` renames into place. Dropping it without `commit`
+/// removes the temporary file, so a failed or interrupted run never leaves
+/// half a file behind. Without a path (or with `-`) it is stdout.
+pub struct OutputFile {
+    target: Option<(TempPath, PathBuf)>,
+    no_clobber: bool,
+}
+
+impl OutputFile {
+    pub fn create(
+        output: Option<&str>,
+        no_clobber: bool,
+    ) -> anyhow::Result<(Self, Box<dyn Write>)> {
+        let path = match output {
+            None | Some("-") => {
+                let file = Self {
+                    target: None,
+                    no_clobber,
+                };
+                return Ok((file, Box::new(std::io::stdout().lock())));
+            }
+            Some(path) => PathBuf::from(path),
+        };
+        // 先检查一次, 避免处理完整个输入之后才失败; commit 时还会再检查
+        if no_clobber && path.exists() {
+            return Err(anyhow!("{} already exists (--no-clobber)", path.display()));
+        }
+        // 临时文件必须和目标在同一个目录, rename 才是原子的
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(".rcli-").suffix(".tmp");
+        // 覆盖已有文件时沿用它的权限, 不能把 0600 的文件变成所有人可读
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(match std::fs::metadata(&path) {
+                Ok(meta) => meta.permissions(),
+                Err(_) => std::fs::Permissions::from_mode(0o644),
+            });
+        }
+        let (handle, temp) = builder
+            .tempfile_in(dir)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?
+            .into_parts();
+        let file = Self {
+            target: Some((temp, path)),
+            no_clobber,
+        };
+        Ok((file, Box::new(BufWriter::new(handle))))
+    }
+
+    /// Open the unfinished file again to write more at its end, for callers
+    /// that have to close it in between
+    pub fn reopen(&self) -> anyhow::Result<Box<dyn Write>> {
+        let Some((temp, _)) = &self.target else {
+            return Ok(Box::new(std::io::stdout().lock()));
+        };
+        let file = OpenOptions::new().append(true).open(temp)?;
+        Ok(Box::new(BufWriter::new(file)))
+    }
+
+    /// Move the finished file into place, call it after the writer is flushed
+    pub fn commit(self) -> anyhow::Result<()> {
+        let Some((temp, path)) = self.target else {
+            return Ok(());
+        };
+        let result = if self.no_clobber {
+            temp.persist_noclobber(&path)
+        } else {
+            temp.persist(&path)
+        };
+        result.map_err(|e| anyhow!("{}: {}", path.display(), e.error))
+    }
+}
+
+/// Rows/bytes progress on stderr, redrawn in place at most a few times per second
+pub struct Progress {
+    enabled: bool,
+    last: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            last: None,
+        }
+    }
+
+    pub fn update(&mut self, rows: u64, bytes: u64) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < Duration::from_millis(200))
+        {
+            return;
+        }
+        self.last = Some(now);
+        eprint!("\r{} rows, {} processed", rows, format_bytes(bytes));
+    }
+
+    pub fn finish(&mut self, rows: u64, bytes: u64) {
+        if self.enabled {
+            eprintln!("\r{} rows, {} processed", rows, format_bytes(bytes));
+        }
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn test_output_file_is_atomic() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out.csv");
+        let path_str = path.to_string_lossy();
+
+        // 没有 commit 时目标文件不存在, 临时文件也被删掉
+        let (file, mut writer) = OutputFile::create(Some(&path_str), false)?;
+        writer.write_all(b"half")?;
+        drop(writer);
+        drop(file);
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+
+        let (file, mut writer) = OutputFile::create(Some(&path_str), false)?;
+        writer.write_all(b"a,b\n")?;
+        writer.flush()?;
+        file.commit()?;
+        assert_eq!(std::fs::read_to_string(&path)?, "a,b\n");
+
+        let err = OutputFile::create(Some(&path_str), true).err().unwrap();
+        assert!(err.to_string().contains("already exists"), "{}", err);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_file_keeps_permissions() -> anyhow::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("secrets.csv");
+        std::fs::write(&path, "old\n")?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+        let (file, mut writer) = OutputFile::create(Some(&path.to_string_lossy()), false)?;
+        writer.write_all(b"new\n")?;
+        writer.flush()?;
+        file.commit()?;
+        assert_eq!(std::fs::read_to_string(&path)?, "new\n");
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        Ok(())
+    }
+}